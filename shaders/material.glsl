@@ -0,0 +1,30 @@
+// How particles are colored by their material, the same in `vs` and `grid_cs`.
+
+// Keep in sync with `MATERIAL_ID_BITS` in `material.rs`.
+const uint MATERIAL_ID_BITS = 8;
+
+// How each material is drawn, indexed by material id. See `MaterialStyle` in `material.rs`.
+struct MaterialStyle {
+    vec4 color;
+    float variation;
+    float emissive;
+    float velocity_tint;
+    float heat_tint;
+};
+
+uint material_id(uint material) {
+    return material & ((1u << MATERIAL_ID_BITS) - 1u);
+}
+
+// The color of a particle drawn with `style`, given the fastest a particle can go.
+vec4 material_color(MaterialStyle style, uint material, vec2 vel, float heat, float max_speed) {
+    // Every grain gets its own shade from the random top bits of `material`.
+    float grain = float(material >> 16) / 65535.0 * 2.0 - 1.0;
+    vec3 color = style.color.rgb * (1.0 + style.variation * grain);
+
+    color += style.velocity_tint * sqrt(length(vel) / max_speed);
+    color *= 1.0 - style.heat_tint * (1.0 - clamp(heat, 0.0, 1.0));
+    color *= 1.0 + style.emissive;
+
+    return vec4(color, style.color.a);
+}
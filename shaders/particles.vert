@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec2 vel;
+layout(location = 2) in uint material;
+layout(location = 3) in float heat;
+
+layout(location = 0) out vec4 outColor;
+
+#define SIM_PARAMS_SET 0
+#define SIM_PARAMS_BINDING 0
+#include <sim_params.glsl>
+#include <material.glsl>
+
+layout(set = 0, binding = 1) readonly buffer Palette {
+    MaterialStyle palette[];
+};
+
+void main() {
+    gl_Position = vec4(pos, 0.0, 1.0);
+    gl_PointSize = 1.0;
+
+    MaterialStyle style = palette[material_id(material)];
+    outColor = material_color(style, material, vel, heat, params.max_speed);
+}
@@ -0,0 +1,16 @@
+// Simulation parameters that can change at runtime, written every frame by `App::step` and read
+// by every shader that moves or colors particles. Edge arrays are ordered left, right, top,
+// bottom. Define `SIM_PARAMS_SET` and `SIM_PARAMS_BINDING` before including this.
+layout(set = SIM_PARAMS_SET, binding = SIM_PARAMS_BINDING) uniform SimParams {
+    uvec4 edge_mode;
+    vec4 edge_restitution;
+    vec2 gravity;
+    float max_speed;
+    float friction;
+    uint attractor_count;
+    float wind_drag;
+    uint explosion_count;
+    float chain_radius;
+    float chain_strength;
+    uint sink_count;
+} params;
@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::engine::{
+    config::{DeviceSelector, PresentMode},
+    save::DEFAULT_SAVE_PATH,
+    Config,
+};
+
+/// A falling sand simulation running on the GPU.
+#[derive(Parser, Debug)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// TOML file to read settings from, `sand.toml` if it exists by default. Options given here
+    /// take precedence over it.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Which logs to show, such as `debug` or `info,rusty_sand_sim::engine::memory=trace`.
+    /// Overrides `SAND_LOG`.
+    #[arg(long, global = true)]
+    pub log: Option<String>,
+    /// File to also write the logs to, as one JSON object per line. Overrides `SAND_LOG_FILE`.
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Open a window and play with the simulation, the default without a command.
+    Run(RunArgs),
+    /// Simulate a number of ticks without a window, then save the world.
+    Headless(HeadlessArgs),
+    /// Measure how long ticks take without a window.
+    Bench(BenchArgs),
+    /// List the GPUs, numbered as `--device` takes them, and what they can do.
+    ListDevices,
+    /// Convert a save file between the binary format and JSON, as told by the file extensions.
+    Convert {
+        input: PathBuf,
+        /// Written as JSON if it ends in `.json`.
+        output: PathBuf,
+    },
+}
+
+/// Options of every command that simulates.
+#[derive(Args, Debug, Clone)]
+pub struct SimArgs {
+    /// Stop spawning once there are this many particles.
+    #[arg(long)]
+    pub max_particles: Option<u32>,
+    /// GPU to run on: its index as printed by `list-devices`, its type (discrete-gpu,
+    /// integrated-gpu, virtual-gpu or cpu) or part of its name.
+    #[arg(long)]
+    pub device: Option<DeviceSelector>,
+    /// Save file to start from.
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+    /// Seed of all randomness, so runs can be repeated.
+    #[arg(long)]
+    pub seed: Option<u32>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub sim: SimArgs,
+    /// Width of the window in pixels.
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Height of the window in pixels.
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// How finished frames are shown.
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+    /// Load the simulation and particle shaders from the GLSL files in this directory, such as
+    /// `shaders`, and reload them whenever they change.
+    #[cfg(feature = "shader-reload")]
+    #[arg(long)]
+    pub shaders: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct HeadlessArgs {
+    #[command(flatten)]
+    pub sim: SimArgs,
+    /// Number of ticks to simulate, each a sixtieth of a second.
+    #[arg(long, default_value_t = 600)]
+    pub ticks: u64,
+    /// Where to save the world afterwards, as JSON if it ends in `.json`.
+    #[arg(long, short, default_value = DEFAULT_SAVE_PATH)]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    pub sim: SimArgs,
+    /// Number of ticks to time.
+    #[arg(long, default_value_t = 1000)]
+    pub ticks: u64,
+    /// Grains of sand to fill the world with, unless starting from a scene.
+    #[arg(long, default_value_t = 100_000)]
+    pub particles: u32,
+}
+
+impl Command {
+    /// The options about the simulation, for the commands that simulate.
+    pub fn sim(&self) -> Option<&SimArgs> {
+        match self {
+            Command::Run(args) => Some(&args.sim),
+            Command::Headless(args) => Some(&args.sim),
+            Command::Bench(args) => Some(&args.sim),
+            Command::ListDevices | Command::Convert { .. } => None,
+        }
+    }
+
+    /// Overrides the settings read from the config file with the ones given here.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(sim) = self.sim() {
+            if let Some(max_particles) = sim.max_particles {
+                config.limits.max_particles = max_particles;
+            }
+            if let Some(device) = &sim.device {
+                config.gpu.device = Some(device.clone());
+            }
+            config.seed = sim.seed.unwrap_or(config.seed);
+        }
+        if let Command::Run(args) = self {
+            let window = &mut config.window;
+            window.width = args.width.unwrap_or(window.width);
+            window.height = args.height.unwrap_or(window.height);
+            window.present_mode = args.present_mode.unwrap_or(window.present_mode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn runs_without_a_command() {
+        let cli = Cli::parse_from(["sand", "--width", "640", "--present-mode", "mailbox"]);
+        assert!(cli.command.is_none());
+
+        let mut config = Config::default();
+        Command::Run(cli.run).apply(&mut config);
+        assert_eq!(config.window.width, 640);
+        assert_eq!(config.window.present_mode, PresentMode::Mailbox);
+        assert_eq!(config.window.height, Config::default().window.height);
+    }
+
+    #[test]
+    fn overrides_config() {
+        let cli = Cli::parse_from([
+            "sand",
+            "headless",
+            "--ticks",
+            "10",
+            "--max-particles",
+            "50",
+            "--seed",
+            "7",
+        ]);
+        let Some(command @ Command::Headless(args)) = &cli.command else {
+            panic!("expected headless, got {:?}", cli.command);
+        };
+        assert_eq!(args.ticks, 10);
+
+        let mut config = Config::default();
+        command.apply(&mut config);
+        assert_eq!(config.limits.max_particles, 50);
+        assert_eq!(config.seed, 7);
+    }
+
+    #[test]
+    fn selects_device() {
+        let cli = Cli::parse_from(["sand", "bench", "--device", "llvmpipe"]);
+        let mut config = Config::default();
+        cli.command.unwrap().apply(&mut config);
+        assert_eq!(
+            config.gpu.device,
+            Some(DeviceSelector::Name("llvmpipe".into()))
+        );
+    }
+}
@@ -1,24 +1,157 @@
 pub mod app;
+pub(crate) mod attractor;
+pub(crate) mod boundary;
+pub mod config;
+pub(crate) mod debug_view;
+pub(crate) mod device;
+pub(crate) mod emitter;
+pub(crate) mod error;
+pub(crate) mod explosion;
+pub(crate) mod font;
+pub(crate) mod grid;
+pub(crate) mod hud;
+pub(crate) mod keys;
+pub(crate) mod material;
 pub(crate) mod memory;
+pub(crate) mod obstacles;
+pub(crate) mod physics;
+pub(crate) mod pipeline;
+pub(crate) mod post;
+pub(crate) mod render;
 pub(crate) mod render_context;
+pub(crate) mod rigid_body;
+pub(crate) mod save;
+#[cfg(feature = "shader-reload")]
+pub(crate) mod shader_reload;
 pub(crate) mod shaders;
+pub(crate) mod spatial_hash;
+pub(crate) mod tool;
+pub(crate) mod trails;
+pub(crate) mod ui;
+pub(crate) mod watch;
+pub(crate) mod wind;
 
 use std::collections::HashSet;
 
 pub use app::App;
+pub use config::{Config, DEFAULT_CONFIG_PATH};
+pub use error::Error;
+pub(crate) use material::Material;
 pub(crate) use memory::DynMemoryManager;
 pub(crate) use render_context::RenderContext;
+pub(crate) use spatial_hash::SpatialHash;
 
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
 use winit::event::{ElementState, MouseButton, WindowEvent};
 
-#[derive(BufferContents, Vertex, Debug)]
+// Cheap integer hash, for randomness that is the same every run.
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^ (x >> 16)
+}
+
+/// Randomness of the simulation, playing out the same every run with the same seed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Rng {
+    seed: u32,
+    // Counts the particles created so far, each one hashing it into its shade.
+    grains: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Self { seed, grains: 0 }
+    }
+
+    /// A random number for `x`, the same for the same `x` and seed.
+    pub fn hash(&self, x: u32) -> u32 {
+        hash(x ^ self.seed)
+    }
+
+    // The random shade of the next particle created.
+    fn next_grain(&mut self) -> u32 {
+        let grain = self.hash(self.grains);
+        self.grains = self.grains.wrapping_add(1);
+        grain
+    }
+}
+
+// Where `cs` parks the particles it removes until the buffer is compacted. Keep in sync with
+// `DEAD` in `cs`.
+const DEAD: f32 = 1.0e6;
+
+#[derive(BufferContents, Vertex, Debug, Clone, Copy)]
 #[repr(C)]
 pub(crate) struct MyVertex {
     #[format(R32G32_SFLOAT)]
     pos: [f32; 2],
     #[format(R32G32_SFLOAT)]
     vel: [f32; 2],
+    // Id of a `Material`, and a random shade in the bits above `MATERIAL_ID_BITS`.
+    #[format(R32_UINT)]
+    material: u32,
+    #[format(R32_SFLOAT)]
+    heat: f32,
+}
+
+impl MyVertex {
+    fn new(pos: [f32; 2], vel: [f32; 2], material: Material, rng: &mut Rng) -> Self {
+        let grain = rng.next_grain();
+        Self {
+            pos,
+            vel,
+            material: material.id() | grain << material::MATERIAL_ID_BITS,
+            heat: material.initial_heat(),
+        }
+    }
+
+    // Only fire and smoke have heat, which they lose until they burn out.
+    fn is_burning(&self) -> bool {
+        self.heat > 0.0
+    }
+
+    fn is_dead(&self) -> bool {
+        self.pos[0] >= DEAD
+    }
+}
+
+// Vertex of the triangles solid obstacles are drawn with.
+#[derive(BufferContents, Vertex, Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct SolidVertex {
+    #[format(R32G32_SFLOAT)]
+    pos: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+impl SolidVertex {
+    // The two triangles covering the obstacle cell at `x`, `y`.
+    fn cell_quad(x: usize, y: usize, color: [f32; 4]) -> [Self; 6] {
+        let [cx, cy] = obstacles::cell_center(x, y);
+        let h = 0.5 * obstacles::CELL_SIZE;
+        [
+            [cx - h, cy - h],
+            [cx + h, cy - h],
+            [cx + h, cy + h],
+            [cx - h, cy - h],
+            [cx + h, cy + h],
+            [cx - h, cy + h],
+        ]
+        .map(|pos| Self { pos, color })
+    }
+
+    // A square of 3 by 3 cells centered on the cell at `x`, `y`, marking a point in the world.
+    fn marker(x: usize, y: usize, color: [f32; 4]) -> impl Iterator<Item = Self> {
+        let last = obstacles::OBSTACLE_GRID_SIZE - 1;
+        (y.saturating_sub(1)..=(y + 1).min(last)).flat_map(move |y| {
+            (x.saturating_sub(1)..=(x + 1).min(last))
+                .flat_map(move |x| Self::cell_quad(x, y, color))
+        })
+    }
 }
 
 pub(crate) struct MouseState {
@@ -1,197 +1,314 @@
-use std::{sync::Arc, time::SystemTime};
+use std::{
+    mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tracing::{debug_span, error, info, warn};
 use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage, Subbuffer,
+    },
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        RenderPassBeginInfo,
+        PrimaryAutoCommandBuffer, RenderPassBeginInfo,
     },
-    descriptor_set::allocator::StandardDescriptorSetAllocator,
-    device::{
-        physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, DeviceOwned,
-        Queue, QueueCreateInfo, QueueFlags,
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
     },
+    device::{Device, DeviceCreateInfo, DeviceOwned, Queue, QueueCreateInfo},
     image::{view::ImageView, Image, ImageUsage},
-    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
-    memory::allocator::StandardMemoryAllocator,
+    instance::Instance,
+    memory::allocator::{MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
-        compute::ComputePipelineCreateInfo,
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            input_assembly::{InputAssemblyState, PrimitiveTopology},
-            multisample::MultisampleState,
-            rasterization::RasterizationState,
+            input_assembly::PrimitiveTopology,
             vertex_input::{Vertex, VertexDefinition},
-            viewport::{Viewport, ViewportState},
-            GraphicsPipelineCreateInfo,
         },
-        layout::PipelineDescriptorSetLayoutCreateInfo,
-        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
-        PipelineShaderStageCreateInfo,
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
     },
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
     shader::EntryPoint,
     swapchain::{
         acquire_next_image, PresentMode, Surface, Swapchain, SwapchainCreateInfo,
         SwapchainPresentInfo,
     },
     sync::{self, GpuFuture},
-    Validated, VulkanError, VulkanLibrary,
+    DeviceSize, Validated, VulkanError,
 };
 use winit::{
     application::ApplicationHandler,
     dpi::{PhysicalPosition, PhysicalSize},
     event::{MouseButton, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+    raw_window_handle::HasDisplayHandle,
     window::{Window, WindowId},
 };
 
+#[cfg(feature = "shader-reload")]
+use super::shader_reload::{ShaderReloader, Shaders};
 use super::{
-    shaders::{cs, fs, vs},
-    DynMemoryManager, MouseState, MyVertex, RenderContext,
+    attractor::{self, Attractor, MAX_ATTRACTORS},
+    boundary::{Boundaries, Boundary},
+    config::DeviceSelector,
+    debug_view::DebugRenderer,
+    device,
+    emitter::{self, Emitter, Sink, MAX_SINKS},
+    error::Error,
+    explosion::{ChainReaction, ExplosionSettings},
+    grid::GridRenderer,
+    hud::{self, Canvas, FrameStats, Hud},
+    keys::{Action, KeyBindings},
+    material::Palette,
+    obstacles::{self, ObstacleMask, WallBrush, WALL_COLOR},
+    physics::{PhysicsParams, Tweak},
+    pipeline::{new_compute_pipeline, new_graphics_pipeline},
+    post::PostProcess,
+    render::{DebugView, RenderMode, RenderSettings},
+    rigid_body::{self, BodySettings, RigidBody},
+    save::{Snapshot, DEFAULT_SAVE_PATH},
+    shaders::{compact_cs, cs, debug_vs, fs, neighbour_cs, solid_vs, vs},
+    tool::Tool,
+    trails::Trails,
+    ui::Ui,
+    watch::FileWatcher,
+    wind::{WindBrush, WindField},
+    Config, DynMemoryManager, Material, MouseState, MyVertex, RenderContext, Rng, SolidVertex,
+    SpatialHash,
 };
 
 // const PARTICLE_COUNT: usize = 819_200;
 
+// Particles removed through open edges or sinks or burnt out are dropped from the buffer every
+// this many frames, if any could have died since the last time.
+const COMPACT_INTERVAL: u64 = 30;
+
+// Simulated seconds the hottest fire takes to burn out, going by `FIRE_COOLING`, `SMOKE_HEAT` and
+// `SMOKE_FADING` in `cs`.
+const BURN_OUT_TIME: f32 = 2.0;
+
+// The world is copied back from the GPU every this many frames, to be restored if the device is
+// lost. The frame taking the backup waits for the whole particle buffer to be read back.
+const BACKUP_INTERVAL: u64 = 600;
+
+// Time simulated by each tick when running without a window.
+const TICK_TIME: f32 = 1.0 / 60.0;
+
+// How far from the cursor, in normalized device coordinates, a right click removes attractors,
+// emitters and sinks.
+const ATTRACTOR_PICK_RADIUS: f32 = 0.1;
+
+// Emitters and sinks are drawn as small squares of these colors.
+const EMITTER_COLOR: [f32; 4] = [0.3, 0.7, 1.0, 1.0];
+const SINK_COLOR: [f32; 4] = [0.35, 0.1, 0.45, 1.0];
+
+// Buttons of the debug panel that need the whole app, so they are handled between frames.
+enum PanelAction {
+    Save,
+    Load,
+}
+
+// The config file, applied again whenever it changes.
+struct ConfigFile {
+    path: PathBuf,
+    watcher: FileWatcher,
+    // Applied to what is read from the file, so options given on the command line keep winning.
+    overrides: Box<dyn Fn(&mut Config)>,
+}
+
 pub struct App {
     instance: Arc<Instance>,
+    // The device picked in the config, picked again when it is lost.
+    device_selector: Option<DeviceSelector>,
     device: Arc<Device>,
     queue: Arc<Queue>,
     memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    frame_buffer_allocator: SubbufferAllocator,
     vertex_memory_mng: DynMemoryManager,
+    spatial_hash: SpatialHash,
     compute_pipeline: Arc<ComputePipeline>,
+    chain_reaction: ChainReaction,
+    boundaries: Boundaries,
+    physics: PhysicsParams,
+    attractors: Vec<Attractor>,
+    wind: WindField,
+    wind_brush: WindBrush,
+    bodies: Vec<RigidBody>,
+    body_settings: BodySettings,
+    // Walls drawn with the wall tool, and the triangles they are drawn with.
+    walls: ObstacleMask,
+    wall_vertices: Vec<SolidVertex>,
+    wall_brush: WallBrush,
+    // Where the current line or rectangle of the wall tool started.
+    wall_drag_start: Option<[f32; 2]>,
+    // Walls and the cells covered by rigid bodies, rebuilt every frame.
+    obstacles: ObstacleMask,
+    emitters: Vec<Emitter>,
+    sinks: Vec<Sink>,
+    explosion_settings: ExplosionSettings,
+    // Explosions set off with the explosion tool since the last frame.
+    pending_explosions: Vec<[f32; 2]>,
+    tool: Tool,
+    // What the spawn tool spawns.
+    material: Material,
+    palette: Palette,
+    render: RenderSettings,
+    keys: KeyBindings,
+    // The simulation stands still while paused, though the world can still be edited.
+    paused: bool,
+    show_hud: bool,
+    frame_stats: FrameStats,
+    ui: Ui,
+    panel_action: Option<PanelAction>,
+    // Cursor position on the previous frame while dragging with the wind or wall tool.
+    last_drag_pos: Option<[f32; 2]>,
+    frame_count: u64,
+    // Whether particles could have died since the last compaction, which waits for the GPU and is
+    // skipped otherwise.
+    may_have_died: bool,
+    // Time until everything set on fire so far has surely burnt out.
+    burn_time: f32,
+    rng: Rng,
+    // The settings from the config file and the command line, as last applied. Reloading the file
+    // only replaces the sections that changed since, keeping the changes made while running to
+    // the others.
+    config: Config,
+    config_file: Option<ConfigFile>,
+    // Compiles the shaders from files instead, while developing them.
+    #[cfg(feature = "shader-reload")]
+    shader_reloader: Option<ShaderReloader>,
     rcx: Option<RenderContext>,
+    // The world as of the last backup, see `BACKUP_INTERVAL`.
+    backup: Option<Snapshot>,
+    // An error from the last frame, handled in `about_to_wait`, or the one that stopped the
+    // event loop.
+    error: Option<Error>,
 }
 
 impl App {
-    pub fn new(event_loop: &EventLoop<()>) -> Self {
-        let library = VulkanLibrary::new().unwrap();
-        let required_extensions = Surface::required_extensions(event_loop).unwrap();
-        let instance = Instance::new(
-            library,
-            InstanceCreateInfo {
-                enabled_extensions: required_extensions,
-                flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
-                ..Default::default()
-            },
-        )
-        .unwrap();
-
-        let device_extensions = DeviceExtensions {
-            khr_swapchain: true,
-            ..DeviceExtensions::empty()
-        };
-        let (physical_device, queue_family_index) = instance
-            .enumerate_physical_devices()
-            .unwrap()
-            .filter(|p| p.supported_extensions().contains(&device_extensions))
-            .filter_map(|p| {
-                p.queue_family_properties()
-                    .iter()
-                    .enumerate()
-                    .position(|(i, q)| {
-                        q.queue_flags.intersects(QueueFlags::GRAPHICS)
-                            && p.presentation_support(i as u32, event_loop).unwrap()
-                    })
-                    .map(|i| (p, i as u32))
-            })
-            .min_by_key(|(p, _)| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-                _ => 5,
-            })
-            .unwrap();
-
-        println!(
-            "Using device: {} (type: {:?})",
-            physical_device.properties().device_name,
-            physical_device.properties().device_type,
-        );
-
-        let (device, mut queues) = Device::new(
-            physical_device,
-            DeviceCreateInfo {
-                enabled_extensions: device_extensions,
-                queue_create_infos: vec![QueueCreateInfo {
-                    queue_family_index,
-                    ..Default::default()
-                }],
-                ..Default::default()
-            },
-        )
-        .unwrap();
-
-        let queue = queues.next().unwrap();
+    pub fn new(event_loop: &EventLoop<()>, config: Config) -> Result<Self, Error> {
+        Self::with_display(Some(event_loop), config)
+    }
 
-        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
-        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
-            device.clone(),
-            Default::default(),
-        ));
-        let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
-            device.clone(),
-            Default::default(),
-        ));
+    /// Creates an app that never opens a window, only simulating, see `run_headless`.
+    pub fn new_headless(config: Config) -> Result<Self, Error> {
+        Self::with_display(None, config)
+    }
 
-        // Create a compute-pipeline for applying the compute shader to vertices.
-        let compute_pipeline = {
-            let cs = cs::load(device.clone())
-                .unwrap()
-                .entry_point("main")
-                .unwrap();
-            let stage = PipelineShaderStageCreateInfo::new(cs);
-            let layout = PipelineLayout::new(
-                device.clone(),
-                PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
-                    .into_pipeline_layout_create_info(device.clone())
-                    .unwrap(),
-            )
-            .unwrap();
+    fn with_display(display: Option<&dyn HasDisplayHandle>, config: Config) -> Result<Self, Error> {
+        let instance = device::new_instance(display)?;
 
-            ComputePipeline::new(
-                device.clone(),
-                None,
-                ComputePipelineCreateInfo::stage_layout(stage, layout),
-            )
-            .unwrap()
-        };
+        let Gpu {
+            device,
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            frame_buffer_allocator,
+            vertex_memory_mng,
+            spatial_hash,
+            compute_pipeline,
+            chain_reaction,
+        } = Gpu::new(
+            &instance,
+            display,
+            config.gpu.device.as_ref(),
+            config.limits.max_particles,
+        )?;
 
-        // Apply scoped logic to create `DeviceLocalBuffer` initialized with vertex data.
-        let vertex_memory_mng = DynMemoryManager::new(
-            memory_allocator.clone(),
-            descriptor_set_allocator.clone(),
-            command_buffer_allocator.clone(),
-            queue.clone(),
-            compute_pipeline.clone(),
-        );
+        let mut attractors = config.attractors.clone();
+        attractors.truncate(MAX_ATTRACTORS);
+        let mut sinks = config.sinks.clone();
+        sinks.truncate(MAX_SINKS);
 
-        App {
+        Ok(App {
             instance,
+            device_selector: config.gpu.device.clone(),
             device,
             queue,
             memory_allocator,
+            descriptor_set_allocator,
             command_buffer_allocator,
+            frame_buffer_allocator,
             vertex_memory_mng,
+            spatial_hash,
             compute_pipeline,
+            chain_reaction,
+            boundaries: config.boundaries,
+            physics: config.physics,
+            attractors,
+            wind: WindField::new(),
+            wind_brush: config.wind,
+            bodies: Vec::new(),
+            body_settings: config.bodies,
+            walls: ObstacleMask::new(),
+            wall_vertices: Vec::new(),
+            wall_brush: config.walls,
+            wall_drag_start: None,
+            obstacles: ObstacleMask::new(),
+            emitters: config.emitters.clone(),
+            sinks,
+            explosion_settings: config.explosions,
+            pending_explosions: Vec::new(),
+            tool: Tool::Spawn,
+            material: Material::Sand,
+            palette: Palette::new(&config.materials),
+            render: config.render,
+            keys: KeyBindings::new(&config.keys),
+            paused: false,
+            show_hud: true,
+            frame_stats: FrameStats::new(),
+            ui: Ui::new(),
+            panel_action: None,
+            last_drag_pos: None,
+            frame_count: 0,
+            may_have_died: false,
+            burn_time: 0.0,
+            rng: Rng::new(config.seed),
+            config,
+            config_file: None,
+            #[cfg(feature = "shader-reload")]
+            shader_reloader: None,
             rcx: None,
+            backup: None,
+            error: None,
+        })
+    }
+
+    /// Applies the config file at `path` again whenever it changes. `overrides` is applied to it
+    /// every time, as it was to the config the app was created with.
+    pub fn watch_config(&mut self, path: PathBuf, overrides: impl Fn(&mut Config) + 'static) {
+        self.config_file = Some(ConfigFile {
+            watcher: FileWatcher::new([path.clone()]),
+            path,
+            overrides: Box::new(overrides),
+        });
+    }
+
+    /// Compiles the simulation and particle shaders from the GLSL files in `dir` once the window
+    /// is open, and again whenever they change. Without a shader compiler, this is logged and the
+    /// built-in shaders are kept.
+    #[cfg(feature = "shader-reload")]
+    pub fn watch_shaders(&mut self, dir: PathBuf) {
+        self.shader_reloader = ShaderReloader::new(dir);
+        if self.shader_reloader.is_none() {
+            error!("failed to create the shader compiler, shaders won't be reloaded");
         }
     }
-}
 
-impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = Arc::new(
-            event_loop
-                .create_window(
-                    Window::default_attributes()
-                        // For simplicity, we are going to assert that the window size is static.
-                        .with_title("simple particles"),
-                )
-                .unwrap(),
-        );
-        let surface = Surface::from_window(self.instance.clone(), window.clone()).unwrap();
+    /// Ends the app, returning the error that stopped the event loop, if any.
+    pub fn finish(self) -> Result<(), Error> {
+        self.error.map_or(Ok(()), Err)
+    }
+
+    /// Creates everything drawing to `window`.
+    fn new_render_context(&self, window: Arc<Window>) -> Result<RenderContext, Error> {
+        let surface = Surface::from_window(self.instance.clone(), window.clone())
+            .map_err(|e| Error::Swapchain(Box::new(e)))?;
         let window_size = window.inner_size();
 
         let (swapchain, images) = {
@@ -199,12 +316,13 @@ impl ApplicationHandler for App {
                 .device
                 .physical_device()
                 .surface_capabilities(&surface, Default::default())
-                .unwrap();
+                .map_err(Error::swapchain)?;
             let (image_format, _) = self
                 .device
                 .physical_device()
                 .surface_formats(&surface, Default::default())
-                .unwrap()[0];
+                .map_err(Error::swapchain)?[0];
+            let present_mode = self.present_mode(&surface)?;
 
             Swapchain::new(
                 self.device.clone(),
@@ -219,11 +337,11 @@ impl ApplicationHandler for App {
                         .into_iter()
                         .next()
                         .unwrap(),
-                    present_mode: PresentMode::Fifo,
+                    present_mode,
                     ..Default::default()
                 },
             )
-            .unwrap()
+            .map_err(Error::swapchain)?
         };
 
         let render_pass = vulkano::single_pass_renderpass!(
@@ -232,7 +350,7 @@ impl ApplicationHandler for App {
                 color: {
                     format: swapchain.image_format(),
                     samples: 1,
-                    load_op: Clear,
+                    load_op: DontCare,
                     store_op: Store,
                 },
             },
@@ -240,31 +358,62 @@ impl ApplicationHandler for App {
                 color: [color],
                 depth_stencil: {},
             },
-        )
-        .unwrap();
+        )?;
 
-        let vs = vs::load(self.device.clone())
-            .unwrap()
-            .entry_point("main")
-            .unwrap();
-        let fs = fs::load(self.device.clone())
-            .unwrap()
+        let vs = vs::load(self.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(self.device.clone())?.entry_point("main").unwrap();
+        let solid_vs = solid_vs::load(self.device.clone())?
             .entry_point("main")
             .unwrap();
 
-        let (framebuffers, pipeline) = window_size_dependent_setup(
+        // The scene is drawn offscreen, and only composited onto the swapchain images.
+        let post = PostProcess::new(
+            self.memory_allocator.clone(),
+            self.descriptor_set_allocator.clone(),
+            &render_pass,
+            window_size,
+        )?;
+
+        let (framebuffers, pipeline, solid_pipeline) = window_size_dependent_setup(
             window_size,
             &images,
             &render_pass,
-            &self.memory_allocator,
+            post.scene_pass(),
             &vs,
             &fs,
-        );
+            &solid_vs,
+        )?;
         let previous_frame_end = Some(sync::now(self.device.clone()).boxed());
 
+        let grid = GridRenderer::new(
+            self.memory_allocator.clone(),
+            self.descriptor_set_allocator.clone(),
+            post.scene_pass(),
+            window_size,
+            self.render.pixel_scale,
+        )?;
+
+        let trails = Trails::new(
+            self.memory_allocator.clone(),
+            self.descriptor_set_allocator.clone(),
+            post.scene_pass(),
+            window_size,
+            &vs,
+            &fs,
+        )?;
+
+        let hud = Hud::new(&render_pass, window_size)?;
+
+        let debug = DebugRenderer::new(
+            self.descriptor_set_allocator.clone(),
+            post.scene_pass(),
+            window_size,
+            &fs,
+        )?;
+
         let start_time = SystemTime::now();
 
-        self.rcx = Some(RenderContext {
+        Ok(RenderContext {
             window,
             swapchain,
             framebuffers,
@@ -272,13 +421,980 @@ impl ApplicationHandler for App {
             pipeline,
             vs,
             fs,
+            solid_vs,
+            solid_pipeline,
+            grid,
+            trails,
+            debug,
+            hud,
+            post,
             recreate_swapchain: false,
             previous_frame_end,
             start_time,
             last_frame_time: start_time,
             cursor_pos: PhysicalPosition::new(0.0, 0.0),
             mouse_state: MouseState::new(),
-        });
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    // The present mode from the settings, unless the surface doesn't support it. Fifo is the one
+    // mode every surface supports.
+    fn present_mode(&self, surface: &Surface) -> Result<PresentMode, Error> {
+        let present_mode = self.config.window.present_mode.into();
+        let supported = self
+            .device
+            .physical_device()
+            .surface_present_modes(surface, Default::default())
+            .map_err(Error::swapchain)?;
+        if supported.contains(&present_mode) {
+            Ok(present_mode)
+        } else {
+            warn!("present mode {present_mode:?} is not supported, using Fifo");
+            Ok(PresentMode::Fifo)
+        }
+    }
+
+    /// Rebuilds everything on the device after it was lost, then restores the world from the
+    /// last backup.
+    fn recover_device(&mut self, event_loop: &ActiveEventLoop) -> Result<(), Error> {
+        // The old swapchain must be gone before the window gets a new one.
+        let Some(old_rcx) = self.rcx.take() else {
+            return Ok(());
+        };
+        let window = old_rcx.window.clone();
+        drop(old_rcx);
+
+        let Gpu {
+            device,
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            frame_buffer_allocator,
+            vertex_memory_mng,
+            spatial_hash,
+            compute_pipeline,
+            chain_reaction,
+        } = Gpu::new(
+            &self.instance,
+            Some(event_loop),
+            self.device_selector.as_ref(),
+            self.vertex_memory_mng.max_particles(),
+        )?;
+        self.device = device;
+        self.queue = queue;
+        self.memory_allocator = memory_allocator;
+        self.descriptor_set_allocator = descriptor_set_allocator;
+        self.command_buffer_allocator = command_buffer_allocator;
+        self.frame_buffer_allocator = frame_buffer_allocator;
+        self.vertex_memory_mng = vertex_memory_mng;
+        self.spatial_hash = spatial_hash;
+        self.compute_pipeline = compute_pipeline;
+        self.chain_reaction = chain_reaction;
+        self.rcx = Some(self.new_render_context(window)?);
+        // The new device starts out with the built in shaders.
+        #[cfg(feature = "shader-reload")]
+        if let Some(reloader) = &mut self.shader_reloader {
+            reloader.reload();
+        }
+
+        // Like saves, backups hold no removed particles, see `snapshot`.
+        if let Some(backup) = &self.backup {
+            self.walls = backup.walls.clone();
+            self.wall_vertices = wall_vertices(&self.walls);
+            self.vertex_memory_mng.add_particles(&backup.particles)?;
+            if backup.particles.iter().any(MyVertex::is_burning) {
+                self.burn_time = BURN_OUT_TIME;
+            }
+        }
+        info!(
+            particles = self.vertex_memory_mng.size(),
+            "recovered from losing the device"
+        );
+        Ok(())
+    }
+
+    /// Applies the sections of the config file that changed since it was last applied, keeping
+    /// the current settings if it is broken.
+    fn reload_config(&mut self) {
+        let Some(file) = &self.config_file else {
+            return;
+        };
+        let path = &file.path;
+        let mut new = match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!(
+                    "failed to reload {}: {e}, keeping the previous settings",
+                    path.display()
+                );
+                return;
+            }
+        };
+        (file.overrides)(&mut new);
+        let old = mem::replace(&mut self.config, new);
+        let new = &self.config;
+
+        if new.boundaries != old.boundaries {
+            self.boundaries = new.boundaries;
+        }
+        if new.physics != old.physics {
+            self.physics = new.physics;
+        }
+        if new.wind != old.wind {
+            self.wind_brush = new.wind;
+        }
+        if new.bodies != old.bodies {
+            self.body_settings = new.bodies;
+        }
+        if new.explosions != old.explosions {
+            self.explosion_settings = new.explosions;
+        }
+        if new.walls != old.walls {
+            self.wall_brush = new.walls;
+        }
+        // Materials and render settings are uploaded every frame, and the grid follows the pixel
+        // scale by itself.
+        if new.materials != old.materials {
+            self.palette = Palette::new(&new.materials);
+        }
+        if new.render != old.render {
+            self.render = new.render;
+        }
+        if new.keys != old.keys {
+            self.keys = KeyBindings::new(&new.keys);
+        }
+
+        // Only a new present mode needs a new swapchain, and the pipelines with it.
+        let (old, new) = (old.window, new.window);
+        if let Some(rcx) = &mut self.rcx {
+            if new.present_mode != old.present_mode {
+                rcx.recreate_swapchain = true;
+            }
+            if (new.width, new.height) != (old.width, old.height) {
+                let _ = rcx
+                    .window
+                    .request_inner_size(PhysicalSize::new(new.width, new.height));
+            }
+        }
+        info!("reloaded {}", path.display());
+    }
+
+    /// Rebuilds the compute and graphics pipelines with `shaders`. If any of them fails to build,
+    /// such as when the new shaders don't fit the buffers and vertices they are used with, all
+    /// the current ones are kept.
+    #[cfg(feature = "shader-reload")]
+    fn apply_shaders(&mut self, shaders: Shaders) {
+        let Some(rcx) = &mut self.rcx else {
+            return;
+        };
+
+        // Descriptor sets made for the previous pipeline are still bound to the new one.
+        let compute_pipeline = match new_compute_pipeline(&self.device, shaders.cs.clone()) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                error!("{e}, keeping the previous shaders");
+                return;
+            }
+        };
+        let set_count = self.compute_pipeline.layout().set_layouts().len() as u32;
+        if !compute_pipeline
+            .layout()
+            .is_compatible_with(self.compute_pipeline.layout(), set_count)
+        {
+            error!("the new compute shader changed its buffers, keeping the previous shaders");
+            return;
+        }
+        if let Err(e) = set_graphics_shaders(rcx, &shaders) {
+            error!("{e}, keeping the previous shaders");
+            return;
+        }
+
+        self.compute_pipeline = compute_pipeline;
+        info!("reloaded shaders");
+    }
+
+    /// Copies the walls and every particle back from the GPU.
+    // Particles removed since the last compaction are left out.
+    fn snapshot(&self) -> Result<Snapshot, Error> {
+        let mut particles = self.vertex_memory_mng.read_particles()?;
+        particles.retain(|particle| !particle.is_dead());
+        Ok(Snapshot {
+            walls: self.walls.clone(),
+            particles,
+        })
+    }
+
+    /// Writes the walls and every particle to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        self.snapshot()?.save(path).map_err(|source| Error::Save {
+            path: path.to_owned(),
+            source,
+        })?;
+        info!("saved to {}", path.display());
+        Ok(())
+    }
+
+    /// Replaces the walls and particles with the ones saved in `path`.
+    pub fn load(&mut self, path: &Path) -> Result<(), Error> {
+        let mut snapshot = Snapshot::load(path).map_err(|source| Error::Load {
+            path: path.to_owned(),
+            source,
+        })?;
+        // Saves from older versions may still hold removed particles.
+        snapshot.particles.retain(|particle| !particle.is_dead());
+        self.walls = snapshot.walls;
+        self.wall_vertices = wall_vertices(&self.walls);
+        self.vertex_memory_mng.clear();
+        self.vertex_memory_mng.add_particles(&snapshot.particles)?;
+        if snapshot.particles.iter().any(MyVertex::is_burning) {
+            self.burn_time = BURN_OUT_TIME;
+        }
+        info!(
+            particles = snapshot.particles.len(),
+            "loaded {}",
+            path.display()
+        );
+        Ok(())
+    }
+
+    // Saving and loading from the keyboard or the debug panel only reports failures.
+    fn quick_save(&self) {
+        if let Err(e) = self.save(Path::new(DEFAULT_SAVE_PATH)) {
+            error!("{e}");
+        }
+    }
+
+    fn quick_load(&mut self) {
+        if let Err(e) = self.load(Path::new(DEFAULT_SAVE_PATH)) {
+            error!("{e}");
+        }
+    }
+
+    /// Fills the top half of the world with a grid of `count` grains of sand, to have something
+    /// to simulate without a saved world.
+    pub fn spawn_grid(&mut self, count: u32) -> Result<(), Error> {
+        let side = (count as f32).sqrt().ceil() as u32;
+        let particles: Vec<_> = (0..count)
+            .map(|i| {
+                let (x, y) = (i % side, i / side);
+                let pos = [
+                    -0.9 + 1.8 * (x as f32 + 0.5) / side as f32,
+                    -0.9 + 0.9 * (y as f32 + 0.5) / side as f32,
+                ];
+                MyVertex::new(pos, [0.0, 0.0], Material::Sand, &mut self.rng)
+            })
+            .collect();
+        self.vertex_memory_mng.add_particles(&particles)
+    }
+
+    pub fn particle_count(&self) -> u32 {
+        self.vertex_memory_mng.size()
+    }
+
+    /// Simulates `ticks` steps of `TICK_TIME` without drawing anything, waiting for each one to
+    /// finish.
+    pub fn run_headless(&mut self, ticks: u64) -> Result<(), Error> {
+        for _ in 0..ticks {
+            let _tick = debug_span!("tick").entered();
+            self.next_frame()?;
+
+            let mut builder = AutoCommandBufferBuilder::primary(
+                self.command_buffer_allocator.clone(),
+                self.queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit,
+            )?;
+            self.step(&mut builder, Vec::new(), TICK_TIME)?;
+            let command_buffer = builder.build()?;
+
+            let future = sync::now(self.device.clone())
+                .then_execute(self.queue.clone(), command_buffer)?
+                .then_signal_fence_and_flush()?;
+            future.wait(None)?;
+        }
+        Ok(())
+    }
+
+    /// Simulates and draws a frame.
+    fn redraw(&mut self, rcx: &mut RenderContext) -> Result<(), Error> {
+        let _frame = debug_span!("frame").entered();
+        let window_size = rcx.window.inner_size();
+
+        if window_size.width == 0 || window_size.height == 0 {
+            return Ok(());
+        }
+
+        rcx.previous_frame_end.as_mut().unwrap().cleanup_finished();
+
+        if rcx.recreate_swapchain {
+            let present_mode = self.present_mode(rcx.swapchain.surface())?;
+            let recreated = rcx
+                .swapchain
+                .recreate(SwapchainCreateInfo {
+                    image_extent: window_size.into(),
+                    present_mode,
+                    ..rcx.swapchain.create_info()
+                })
+                .map_err(Error::swapchain)
+                .and_then(|(new_swapchain, new_images)| {
+                    rcx.swapchain = new_swapchain;
+                    window_size_dependent_setup(
+                        window_size,
+                        &new_images,
+                        &rcx.render_pass,
+                        rcx.post.scene_pass(),
+                        &rcx.vs,
+                        &rcx.fs,
+                        &rcx.solid_vs,
+                    )
+                });
+            (rcx.framebuffers, rcx.pipeline, rcx.solid_pipeline) = recreated?;
+            rcx.post.resize(&rcx.render_pass, window_size)?;
+            rcx.trails.resize(rcx.post.scene_pass(), window_size)?;
+            rcx.debug.resize(rcx.post.scene_pass(), window_size)?;
+            rcx.hud.resize(&rcx.render_pass, window_size)?;
+            rcx.grid
+                .resize(rcx.post.scene_pass(), window_size, self.render.pixel_scale)?;
+            rcx.recreate_swapchain = false;
+        } else if rcx.grid.pixel_scale() != self.render.pixel_scale {
+            rcx.grid
+                .resize(rcx.post.scene_pass(), window_size, self.render.pixel_scale)?;
+        }
+
+        // Update per-frame variables.
+        let now = SystemTime::now();
+        let _time = now.duration_since(rcx.start_time).unwrap().as_secs_f32();
+        let frame_time = now
+            .duration_since(rcx.last_frame_time)
+            .unwrap()
+            .as_secs_f32();
+        rcx.last_frame_time = now;
+        self.frame_stats.update(frame_time);
+        let delta_time = if self.paused { 0.0 } else { frame_time };
+
+        // New particles are uploaded along with the rest of the frame.
+        let mut spawned = Vec::new();
+        if self.tool == Tool::Spawn && rcx.mouse_state.is_held(MouseButton::Left) {
+            let pos = rcx.cursor_pos.into();
+            spawned.push(MyVertex::new(pos, [0.0, 0.0], self.material, &mut self.rng));
+        }
+
+        // The wind tool blows in the direction the cursor moved since the last frame.
+        if self.tool == Tool::Wind {
+            let pos: [f32; 2] = rcx.cursor_pos.into();
+            if rcx.mouse_state.is_held(MouseButton::Left) {
+                if let Some(last) = self.last_drag_pos {
+                    let delta = [pos[0] - last[0], pos[1] - last[1]];
+                    let length = delta[0].hypot(delta[1]);
+                    if length > 1e-4 {
+                        let scale = self.wind_brush.strength / length;
+                        self.wind
+                            .paint(pos, self.wind_brush.radius, delta.map(|d| d * scale));
+                    }
+                }
+                self.last_drag_pos = Some(pos);
+            } else {
+                self.last_drag_pos = None;
+                if rcx.mouse_state.is_held(MouseButton::Right) {
+                    self.wind.erase(pos, self.wind_brush.radius);
+                }
+            }
+        }
+
+        // Without a modifier held, the wall tool paints along the path of the cursor.
+        if self.tool == Tool::Wall {
+            let pos: [f32; 2] = rcx.cursor_pos.into();
+            let shape = rcx.modifiers.shift_key() || rcx.modifiers.control_key();
+            if rcx.mouse_state.is_held(MouseButton::Left) && !shape {
+                let last = self.last_drag_pos.unwrap_or(pos);
+                self.walls.line(last, pos, self.wall_brush.radius);
+                self.last_drag_pos = Some(pos);
+                self.wall_vertices = wall_vertices(&self.walls);
+            } else {
+                self.last_drag_pos = None;
+                if rcx.mouse_state.is_held(MouseButton::Right) {
+                    self.walls.paint(pos, self.wall_brush.radius, false);
+                    self.wall_vertices = wall_vertices(&self.walls);
+                }
+            }
+        }
+
+        self.next_frame()?;
+        if self.frame_count.is_multiple_of(BACKUP_INTERVAL) {
+            self.backup = Some(self.snapshot()?);
+        }
+
+        // Acquire information on the next swapchain target.
+        let acquire = debug_span!("acquire").entered();
+        let (image_index, suboptimal, acquire_future) = match acquire_next_image(
+            rcx.swapchain.clone(),
+            None, // timeout
+        )
+        .map_err(Validated::unwrap)
+        {
+            Ok(r) => r,
+            Err(VulkanError::OutOfDate) => {
+                rcx.recreate_swapchain = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if suboptimal {
+            rcx.recreate_swapchain = true;
+        }
+        drop(acquire);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        // Only times recording the commands, see `logging::init`.
+        let compute = debug_span!("compute").entered();
+        let sim_params = self.step(&mut builder, spawned, delta_time)?;
+        drop(compute);
+
+        // Walls, bodies, emitters and sinks are drawn as solid triangles.
+        let mut solid_vertices = self.wall_vertices.clone();
+        for body in &self.bodies {
+            body.rasterize(|x, y| {
+                solid_vertices.extend(SolidVertex::cell_quad(x, y, body.color));
+            });
+        }
+        let markers = self
+            .emitters
+            .iter()
+            .map(|e| (e.pos, EMITTER_COLOR))
+            .chain(self.sinks.iter().map(|s| (s.pos, SINK_COLOR)));
+        for (pos, color) in markers {
+            if let Some((x, y)) = obstacles::cell_of(pos) {
+                solid_vertices.extend(SolidVertex::marker(x, y, color));
+            }
+        }
+
+        let palette = self
+            .frame_buffer_allocator
+            .allocate_slice(self.palette.styles().len() as DeviceSize)?;
+        for (dst, src) in palette.write()?.iter_mut().zip(self.palette.styles()) {
+            let [r, g, b] = src.color;
+            *dst = vs::MaterialStyle {
+                color: [r, g, b, 1.0],
+                variation: src.variation,
+                emissive: src.emissive,
+                velocity_tint: src.velocity_tint,
+                heat_tint: src.heat_tint,
+            };
+        }
+        let render_params_set = DescriptorSet::new(
+            self.descriptor_set_allocator.clone(),
+            rcx.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, sim_params.clone()),
+                WriteDescriptorSet::buffer(1, palette.clone()),
+            ],
+            [],
+        )?;
+
+        let render = debug_span!("render").entered();
+        // Debug views replace the render mode, and trails replace drawing the particles
+        // straight into the scene.
+        let debug_view = self.render.debug_view != DebugView::Off;
+        let trails =
+            !debug_view && self.render.mode == RenderMode::Points && self.render.trails.enabled;
+        if trails {
+            rcx.trails.record_accumulate(
+                &mut builder,
+                &self.vertex_memory_mng.device_local_buffer,
+                self.vertex_memory_mng.size(),
+                render_params_set.clone(),
+                &self.render.trails,
+                delta_time,
+            )?;
+        } else {
+            rcx.trails.reset();
+        }
+
+        if !debug_view && self.render.mode == RenderMode::Grid {
+            rcx.grid.record_splat(
+                &mut builder,
+                &self.vertex_memory_mng.device_local_buffer,
+                self.vertex_memory_mng.size(),
+                sim_params.clone(),
+                palette,
+            )?;
+        }
+
+        // Use render-pass to draw particles to the offscreen scene image.
+        builder.begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some([0., 0., 0., 1.].into())],
+                ..RenderPassBeginInfo::framebuffer(rcx.post.scene_framebuffer())
+            },
+            Default::default(),
+        )?;
+
+        match self.render.mode {
+            _ if debug_view => rcx.debug.record_draw(
+                &mut builder,
+                &self.vertex_memory_mng.device_local_buffer,
+                sim_params,
+                self.vertex_memory_mng.hash_buffers.densities(),
+                debug_vs::PushConstants {
+                    view: self.render.debug_view.id(),
+                    particle_count: self.vertex_memory_mng.size(),
+                    rest_density: if self.physics.sph.enabled {
+                        self.physics.sph.rest_density
+                    } else {
+                        0.0
+                    },
+                },
+            )?,
+            RenderMode::Points if trails => rcx.trails.record_draw(&mut builder)?,
+            RenderMode::Points => {
+                builder
+                    .bind_pipeline_graphics(rcx.pipeline.clone())?
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        rcx.pipeline.layout().clone(),
+                        0,
+                        render_params_set,
+                    )?
+                    .bind_vertex_buffers(0, self.vertex_memory_mng.device_local_buffer.clone())?;
+
+                unsafe { builder.draw(self.vertex_memory_mng.size(), 1, 0, 0) }?;
+            }
+            RenderMode::Grid => rcx.grid.record_draw(&mut builder)?,
+        }
+
+        if !solid_vertices.is_empty() {
+            let solid_buffer = self
+                .frame_buffer_allocator
+                .allocate_slice(solid_vertices.len() as DeviceSize)?;
+            solid_buffer.write()?.copy_from_slice(&solid_vertices);
+            builder
+                .bind_pipeline_graphics(rcx.solid_pipeline.clone())?
+                .bind_vertex_buffers(0, solid_buffer)?;
+            unsafe { builder.draw(solid_vertices.len() as u32, 1, 0, 0) }?;
+        }
+
+        builder.end_render_pass(Default::default())?;
+
+        // Add the bloom and draw the scene to the swapchain.
+        rcx.post.record(
+            &mut builder,
+            rcx.framebuffers[image_index as usize].clone(),
+            &self.render.bloom,
+        )?;
+        // Stats and tool state, and the debug panel, drawn over the finished frame.
+        let mut canvas = Canvas::new(window_size);
+        if self.show_hud {
+            let brush = match self.tool {
+                Tool::Wind => Some(self.wind_brush.radius),
+                Tool::Wall => Some(self.wall_brush.radius),
+                Tool::Explosion => Some(self.explosion_settings.radius),
+                _ => None,
+            };
+            let mut lines = vec![
+                format!(
+                    "FPS {:.0} ({:.1} ms)",
+                    self.frame_stats.fps(),
+                    self.frame_stats.frame_time_ms()
+                ),
+                format!(
+                    "particles {} / {}{}",
+                    self.vertex_memory_mng.size(),
+                    self.vertex_memory_mng.capacity(),
+                    if self.vertex_memory_mng.is_full() {
+                        " (budget full)"
+                    } else {
+                        ""
+                    }
+                ),
+                format!("tool {:?}, material {:?}", self.tool, self.material),
+                match brush {
+                    Some(radius) => format!("brush {radius:.3}"),
+                    None => "brush -".to_string(),
+                },
+            ];
+            if self.paused {
+                lines.push("paused".to_string());
+            }
+            hud::stats_panel(&mut canvas, &lines);
+        }
+        if self.ui.visible() {
+            let mut panel = self.ui.panel(&mut canvas, window_size.width as f32);
+            let physics = &mut self.physics;
+            panel.heading("physics");
+            panel.slider("max speed", &mut physics.max_speed, 0.5..=30.0);
+            panel.slider("friction", &mut physics.friction, 0.0..=10.0);
+            panel.slider("gravity x", &mut physics.gravity[0], -30.0..=30.0);
+            panel.slider("gravity y", &mut physics.gravity[1], -30.0..=30.0);
+            panel.slider("wind drag", &mut physics.wind_drag, 0.0..=20.0);
+            if panel.button("collisions", physics.collisions) {
+                physics.collisions = !physics.collisions;
+            }
+            if panel.button("SPH fluid", physics.sph.enabled) {
+                physics.sph.enabled = !physics.sph.enabled;
+            }
+
+            panel.heading("material");
+            for material in Material::ALL {
+                if panel.button(&format!("{material:?}"), material == self.material) {
+                    self.material = material;
+                }
+            }
+
+            panel.heading("world");
+            if panel.button("save", false) {
+                self.panel_action = Some(PanelAction::Save);
+            }
+            if panel.button("load", false) {
+                self.panel_action = Some(PanelAction::Load);
+            }
+
+            let memory = &self.vertex_memory_mng;
+            panel.heading("buffers");
+            panel.label(&format!("particles {}", memory.size()));
+            panel.label(&format!("capacity {}", memory.capacity()));
+            panel.label(&format!("queued {}", memory.queued()));
+            panel.end();
+        }
+        rcx.hud
+            .record_draw(&mut builder, &self.frame_buffer_allocator, &canvas)?;
+        builder.end_render_pass(Default::default())?;
+
+        let command_buffer = builder.build()?;
+        drop(render);
+
+        let _present = debug_span!("present").entered();
+        let future = rcx
+            .previous_frame_end
+            .take()
+            .unwrap()
+            .join(acquire_future)
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_swapchain_present(
+                self.queue.clone(),
+                SwapchainPresentInfo::swapchain_image_index(rcx.swapchain.clone(), image_index),
+            )
+            .then_signal_fence_and_flush();
+
+        match future.map_err(Validated::unwrap) {
+            // Success, store result into vector.
+            Ok(future) => rcx.previous_frame_end = Some(future.boxed()),
+            Err(VulkanError::OutOfDate) => {
+                rcx.recreate_swapchain = true;
+                rcx.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+            }
+            Err(e) => {
+                rcx.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Advances the world by `delta_time`, recording the compute passes that move the particles
+    /// into `builder`. The `spawned` particles are added along with the ones from emitters and
+    /// shattered bodies. Returns the simulation parameters of the step, which the particles are
+    /// drawn with.
+    fn step(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        mut spawned: Vec<MyVertex>,
+        delta_time: f32,
+    ) -> Result<Subbuffer<cs::SimParams>, Error> {
+        for emitter in &mut self.emitters {
+            emitter.emit(delta_time, &mut self.rng, &mut spawned);
+        }
+
+        // Explosives set off on the GPU push bodies away once their frame is read back.
+        let chained = self.chain_reaction.finished_explosions();
+        for explosion in &chained {
+            for body in &mut self.bodies {
+                body.blast(explosion.pos, explosion.radius, explosion.strength);
+            }
+        }
+
+        // Particles die by leaving through an open edge, reaching a sink or burning out.
+        if !self.pending_explosions.is_empty()
+            || !chained.is_empty()
+            || spawned.iter().any(MyVertex::is_burning)
+        {
+            self.burn_time = BURN_OUT_TIME;
+        }
+        self.may_have_died |=
+            self.boundaries.has_open() || !self.sinks.is_empty() || self.burn_time > 0.0;
+        self.burn_time -= delta_time;
+
+        // Bodies that hit something too hard break into loose particles.
+        let shattered = rigid_body::step_bodies(
+            &mut self.bodies,
+            delta_time,
+            self.physics.gravity,
+            &self.body_settings,
+            &self.boundaries,
+            &self.walls,
+        );
+        for body in shattered {
+            spawned.extend(
+                body.fragments()
+                    .map(|(pos, vel)| MyVertex::new(pos, vel, Material::Sand, &mut self.rng)),
+            );
+        }
+        self.vertex_memory_mng.queue_particles(&spawned);
+
+        // Write the walls and bodies into the obstacle mask particles collide with.
+        self.obstacles.clone_from(&self.walls);
+        for body in &self.bodies {
+            body.rasterize(|x, y| self.obstacles.set(x, y));
+        }
+
+        // Upload this step's simulation parameters, read by both `cs` and `vs`.
+        let sim_params = self.frame_buffer_allocator.allocate_sized()?;
+        *sim_params.write()? = cs::SimParams {
+            edge_mode: self.boundaries.modes(),
+            edge_restitution: self.boundaries.restitutions(),
+            gravity: self.physics.gravity,
+            max_speed: self.physics.max_speed,
+            friction: self.physics.friction,
+            attractor_count: self.attractors.len() as u32,
+            wind_drag: self.physics.wind_drag,
+            explosion_count: self.pending_explosions.len() as u32,
+            chain_radius: self.explosion_settings.chain_radius,
+            chain_strength: self.explosion_settings.chain_strength,
+            sink_count: self.sinks.len() as u32,
+        };
+        // Empty buffers are not allowed, so there is always room for one attractor.
+        let attractors = self
+            .frame_buffer_allocator
+            .allocate_slice(self.attractors.len().max(1) as DeviceSize)?;
+        for (dst, src) in attractors.write()?.iter_mut().zip(&self.attractors) {
+            *dst = cs::Attractor {
+                pos: src.pos,
+                strength: src.strength,
+                falloff: src.falloff,
+            };
+        }
+        let wind = self
+            .frame_buffer_allocator
+            .allocate_slice(self.wind.cells().len() as DeviceSize)?;
+        wind.write()?.copy_from_slice(self.wind.cells());
+        let explosions = self
+            .frame_buffer_allocator
+            .allocate_slice(self.pending_explosions.len().max(1) as DeviceSize)?;
+        for (dst, pos) in explosions
+            .write()?
+            .iter_mut()
+            .zip(self.pending_explosions.drain(..))
+        {
+            *dst = self.explosion_settings.at(pos);
+        }
+        let sinks = self
+            .frame_buffer_allocator
+            .allocate_slice(self.sinks.len().max(1) as DeviceSize)?;
+        for (dst, src) in sinks.write()?.iter_mut().zip(&self.sinks) {
+            *dst = [src.pos[0], src.pos[1], src.radius, 0.0];
+        }
+        let obstacles = self
+            .frame_buffer_allocator
+            .allocate_slice(self.obstacles.words().len() as DeviceSize)?;
+        obstacles.write()?.copy_from_slice(self.obstacles.words());
+        let compute_params_set = DescriptorSet::new(
+            self.descriptor_set_allocator.clone(),
+            self.compute_pipeline.layout().set_layouts()[1].clone(),
+            [
+                WriteDescriptorSet::buffer(0, sim_params.clone()),
+                WriteDescriptorSet::buffer(1, attractors),
+                WriteDescriptorSet::buffer(2, wind),
+                WriteDescriptorSet::buffer(3, obstacles),
+                WriteDescriptorSet::buffer(4, explosions),
+                WriteDescriptorSet::buffer(5, sinks),
+            ],
+            [],
+        )?;
+
+        // Upload the particles spawned this step before anything simulates them.
+        self.vertex_memory_mng.record_pending(builder)?;
+
+        // Create push constants to be passed to compute shader.
+        let push_constants = cs::PushConstants {
+            delta_time,
+            particle_count: self.vertex_memory_mng.size(),
+        };
+        let num_workgroups_x = (self.vertex_memory_mng.size() + 1023) / 1024;
+
+        // SPH forces change the velocities the compute shader integrates.
+        if self.physics.sph.enabled {
+            self.spatial_hash.record_sph(
+                builder,
+                &self.vertex_memory_mng.device_local_buffer,
+                &self.vertex_memory_mng.hash_buffers,
+                self.vertex_memory_mng.size(),
+                self.physics.particle_radius,
+                &self.physics.sph,
+                delta_time,
+            )?;
+        }
+
+        let chain_set = self.chain_reaction.record_next(builder)?;
+
+        builder
+            // Push constants for compute shader.
+            .push_constants(self.compute_pipeline.layout().clone(), 0, push_constants)?
+            // Perform compute operation to update particle positions.
+            .bind_pipeline_compute(self.compute_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compute_pipeline.layout().clone(),
+                0, // Bind the vertices to set 0, the parameters to set 1 and the
+                // chain reaction to set 2.
+                vec![
+                    self.vertex_memory_mng.descriptor_set.clone(),
+                    compute_params_set,
+                    chain_set,
+                ],
+            )?;
+        unsafe { builder.dispatch([num_workgroups_x.max(1), 1, 1]) }?;
+        self.chain_reaction.record_readback(builder)?;
+
+        // Separate particles that overlap after moving.
+        if self.physics.collisions && !self.paused {
+            self.spatial_hash.record_collisions(
+                builder,
+                &self.vertex_memory_mng.device_local_buffer,
+                &self.vertex_memory_mng.hash_buffers,
+                self.vertex_memory_mng.size(),
+                self.physics.particle_radius,
+            )?;
+        }
+        Ok(sim_params)
+    }
+
+    // Counts a frame, dropping dead particles from the buffer every `COMPACT_INTERVAL` frames.
+    fn next_frame(&mut self) -> Result<(), Error> {
+        self.frame_count += 1;
+        if self.may_have_died && self.frame_count.is_multiple_of(COMPACT_INTERVAL) {
+            self.vertex_memory_mng.remove_dead()?;
+            self.may_have_died = false;
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyCode, shift: bool) {
+        let Some(action) = self.keys.action(key) else {
+            return;
+        };
+        if let Some(tool) = action.tool() {
+            self.tool = tool;
+            info!("selected tool: {tool:?}");
+            return;
+        }
+
+        match action {
+            Action::RotateLeft | Action::RotateRight => {
+                let degrees = if action == Action::RotateLeft {
+                    -15.0
+                } else {
+                    15.0
+                };
+                self.physics.rotate_gravity(degrees);
+                info!("gravity: {:?}", self.physics.gravity);
+            }
+            Action::Gravity | Action::Friction | Action::MaxSpeed => {
+                let tweak = match action {
+                    Action::Gravity => Tweak::Gravity,
+                    Action::Friction => Tweak::Friction,
+                    _ => Tweak::MaxSpeed,
+                };
+                self.physics.tweak(tweak, !shift);
+                info!("physics: {:?}", self.physics);
+            }
+            Action::Save => self.quick_save(),
+            Action::Load => self.quick_load(),
+            Action::CycleMaterial => {
+                self.material = self.material.cycle();
+                info!("spawning: {:?}", self.material);
+            }
+            Action::RenderMode => {
+                self.render.toggle_mode();
+                info!("render mode: {:?}", self.render.mode);
+            }
+            Action::PixelScale => {
+                self.render.cycle_pixel_scale();
+                info!("grid cells are {} pixels wide", self.render.pixel_scale);
+            }
+            Action::Pause => {
+                self.paused = !self.paused;
+                info!("paused: {}", self.paused);
+            }
+            Action::Hud => self.show_hud = !self.show_hud,
+            Action::Panel => self.ui.toggle(),
+            Action::DebugView => {
+                self.render.debug_view = self.render.debug_view.cycle();
+                info!("debug view: {:?}", self.render.debug_view);
+            }
+            Action::Trails => {
+                let trails = &mut self.render.trails;
+                if shift {
+                    trails.blend = trails.blend.toggle();
+                    info!("trail blend: {:?}", trails.blend);
+                } else {
+                    trails.enabled = !trails.enabled;
+                    info!("trails: {}", trails.enabled);
+                }
+            }
+            Action::Bloom => {
+                self.render.bloom.enabled = !self.render.bloom.enabled;
+                info!("bloom: {}", self.render.bloom.enabled);
+            }
+            Action::Collisions => {
+                self.physics.collisions = !self.physics.collisions;
+                info!("particle collisions: {}", self.physics.collisions);
+            }
+            Action::Sph => {
+                self.physics.sph.enabled = !self.physics.sph.enabled;
+                info!("SPH fluid: {}", self.physics.sph.enabled);
+            }
+            Action::LeftEdge => cycle_edge("left", &mut self.boundaries.left),
+            Action::RightEdge => cycle_edge("right", &mut self.boundaries.right),
+            Action::TopEdge => cycle_edge("top", &mut self.boundaries.top),
+            Action::BottomEdge => cycle_edge("bottom", &mut self.boundaries.bottom),
+            // Handled above.
+            Action::SpawnTool
+            | Action::AttractorTool
+            | Action::WindTool
+            | Action::BodyTool
+            | Action::ExplosionTool
+            | Action::WallTool
+            | Action::EmitterTool => {}
+        }
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window = event_loop.create_window(
+            Window::default_attributes()
+                .with_title("simple particles")
+                .with_inner_size(PhysicalSize::new(
+                    self.config.window.width,
+                    self.config.window.height,
+                )),
+        );
+        let rcx = window
+            .map_err(Error::from)
+            .and_then(|window| self.new_render_context(Arc::new(window)));
+        match rcx {
+            Ok(rcx) => self.rcx = Some(rcx),
+            Err(e) => {
+                self.error = Some(e);
+                event_loop.exit();
+            }
+        }
     }
 
     fn window_event(
@@ -287,7 +1403,10 @@ impl ApplicationHandler for App {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
-        let rcx = self.rcx.as_mut().unwrap();
+        // Nothing is drawn until the window is created, or after creating it failed.
+        let Some(rcx) = self.rcx.as_mut() else {
+            return;
+        };
 
         match event {
             WindowEvent::CloseRequested => {
@@ -300,153 +1419,282 @@ impl ApplicationHandler for App {
                 let window_size = rcx.window.inner_size();
                 rcx.cursor_pos.x = 2.0 * (position.x / window_size.width as f64) - 1.0;
                 rcx.cursor_pos.y = 2.0 * (position.y / window_size.height as f64) - 1.0;
+                self.ui.cursor_moved([position.x as f32, position.y as f32]);
             }
-            WindowEvent::MouseInput { .. } => {
-                rcx.mouse_state.handle_event(&event);
-            }
-            WindowEvent::RedrawRequested => {
-                let window_size = rcx.window.inner_size();
-
-                if window_size.width == 0 || window_size.height == 0 {
+            WindowEvent::MouseInput { state, button, .. } => {
+                // The debug panel takes clicks over it before the tools do.
+                if self.ui.mouse_input(button, state.is_pressed()) {
                     return;
                 }
+                rcx.mouse_state.handle_event(&event);
 
-                rcx.previous_frame_end.as_mut().unwrap().cleanup_finished();
-
-                if rcx.recreate_swapchain {
-                    let (new_swapchain, new_images) = rcx
-                        .swapchain
-                        .recreate(SwapchainCreateInfo {
-                            image_extent: window_size.into(),
-                            ..rcx.swapchain.create_info()
-                        })
-                        .expect("failed to recreate swapchain");
+                if state.is_pressed() && self.tool == Tool::Attractor {
+                    let pos = rcx.cursor_pos.into();
+                    match button {
+                        MouseButton::Left if self.attractors.len() < MAX_ATTRACTORS => {
+                            self.attractors.push(if rcx.modifiers.shift_key() {
+                                Attractor::repeller(pos)
+                            } else {
+                                Attractor::new(pos)
+                            });
+                        }
+                        MouseButton::Right => {
+                            attractor::remove_nearest(
+                                &mut self.attractors,
+                                pos,
+                                ATTRACTOR_PICK_RADIUS,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
 
-                    rcx.swapchain = new_swapchain;
-                    (rcx.framebuffers, rcx.pipeline) = window_size_dependent_setup(
-                        window_size,
-                        &new_images,
-                        &rcx.render_pass,
-                        &self.memory_allocator,
-                        &rcx.vs,
-                        &rcx.fs,
-                    );
-                    rcx.recreate_swapchain = false;
+                // Lines and rectangles of the wall tool span from where the button was pressed to
+                // where it was released.
+                if button == MouseButton::Left && self.tool == Tool::Wall {
+                    let pos = rcx.cursor_pos.into();
+                    if state.is_pressed() {
+                        self.wall_drag_start = Some(pos);
+                    } else if let Some(start) = self.wall_drag_start.take() {
+                        if rcx.modifiers.shift_key() {
+                            self.walls.line(start, pos, self.wall_brush.radius);
+                        } else if rcx.modifiers.control_key() {
+                            self.walls.rect(start, pos);
+                        }
+                        self.wall_vertices = wall_vertices(&self.walls);
+                    }
                 }
 
-                // Update per-frame variables.
-                let now = SystemTime::now();
-                let _time = now.duration_since(rcx.start_time).unwrap().as_secs_f32();
-                let delta_time = now
-                    .duration_since(rcx.last_frame_time)
-                    .unwrap()
-                    .as_secs_f32();
-                rcx.last_frame_time = now;
-
-                if rcx.mouse_state.is_held(MouseButton::Left) {
-                    self.vertex_memory_mng.add_pixels(1, rcx.cursor_pos);
-                    println!("what? {}", self.vertex_memory_mng.size());
+                if state.is_pressed() && self.tool == Tool::Emitter {
+                    let pos = rcx.cursor_pos.into();
+                    match button {
+                        MouseButton::Left if rcx.modifiers.shift_key() => {
+                            if self.sinks.len() < MAX_SINKS {
+                                self.sinks.push(Sink::new(pos));
+                            }
+                        }
+                        MouseButton::Left => {
+                            self.emitters.push(Emitter::new(pos, self.material));
+                        }
+                        MouseButton::Right => {
+                            emitter::remove_nearest(
+                                &mut self.emitters,
+                                &mut self.sinks,
+                                pos,
+                                ATTRACTOR_PICK_RADIUS,
+                            );
+                        }
+                        _ => {}
+                    }
                 }
 
-                // Create push constants to be passed to compute shader.
-                let push_constants = cs::PushConstants { delta_time };
+                if state.is_pressed() && button == MouseButton::Left && self.tool == Tool::Body {
+                    let pos = rcx.cursor_pos.into();
+                    self.bodies.push(if rcx.modifiers.shift_key() {
+                        RigidBody::rock_at(pos, self.rng.hash(self.frame_count as u32))
+                    } else {
+                        RigidBody::crate_at(pos)
+                    });
+                }
 
-                // Acquire information on the next swapchain target.
-                let (image_index, suboptimal, acquire_future) = match acquire_next_image(
-                    rcx.swapchain.clone(),
-                    None, // timeout
-                )
-                .map_err(Validated::unwrap)
+                if state.is_pressed() && button == MouseButton::Left && self.tool == Tool::Explosion
                 {
-                    Ok(r) => r,
-                    Err(VulkanError::OutOfDate) => {
-                        rcx.recreate_swapchain = true;
-                        return;
+                    let pos = rcx.cursor_pos.into();
+                    let settings = &self.explosion_settings;
+                    for body in &mut self.bodies {
+                        body.blast(pos, settings.radius, settings.strength);
                     }
-                    Err(e) => panic!("failed to acquire next image: {e}"),
-                };
-
-                if suboptimal {
-                    rcx.recreate_swapchain = true;
+                    self.pending_explosions.push(pos);
                 }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                rcx.modifiers = modifiers.state();
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    if event.state.is_pressed() && !event.repeat {
+                        let shift = rcx.modifiers.shift_key();
+                        self.handle_key(key, shift);
+                    }
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let mut rcx = self.rcx.take().unwrap();
+                // Lost devices are recovered from in `about_to_wait`, anything else ends the app.
+                if let Err(e) = self.redraw(&mut rcx) {
+                    self.error = Some(e);
+                }
+                self.rcx = Some(rcx);
+            }
+            _ => {}
+        }
+    }
 
-                let num_workgroups_x = (self.vertex_memory_mng.size() + 1023) / 1024;
-
-                let mut builder = AutoCommandBufferBuilder::primary(
-                    self.command_buffer_allocator.clone(),
-                    self.queue.queue_family_index(),
-                    CommandBufferUsage::OneTimeSubmit,
-                )
-                .unwrap();
-
-                builder
-                    // Push constants for compute shader.
-                    .push_constants(self.compute_pipeline.layout().clone(), 0, push_constants)
-                    .unwrap()
-                    // Perform compute operation to update particle positions.
-                    .bind_pipeline_compute(self.compute_pipeline.clone())
-                    .unwrap()
-                    .bind_descriptor_sets(
-                        PipelineBindPoint::Compute,
-                        self.compute_pipeline.layout().clone(),
-                        0, // Bind this descriptor set to index 0.
-                        self.vertex_memory_mng.descriptor_set.clone(),
-                    )
-                    .unwrap();
-                unsafe { builder.dispatch([num_workgroups_x.max(1), 1, 1]) }.unwrap();
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(file) = &mut self.config_file {
+            if file.watcher.changed() {
+                self.reload_config();
+            }
+        }
+        // Shaders are compiled once there are graphics pipelines to replace.
+        #[cfg(feature = "shader-reload")]
+        if self.rcx.is_some() {
+            let reloader = self.shader_reloader.as_mut();
+            if let Some(shaders) = reloader.and_then(|reloader| reloader.poll(&self.device)) {
+                self.apply_shaders(shaders);
+            }
+        }
 
-                // Use render-pass to draw particles to swapchain.
-                builder
-                    .begin_render_pass(
-                        RenderPassBeginInfo {
-                            clear_values: vec![Some([0., 0., 0., 1.].into())],
-                            ..RenderPassBeginInfo::framebuffer(
-                                rcx.framebuffers[image_index as usize].clone(),
-                            )
-                        },
-                        Default::default(),
-                    )
-                    .unwrap()
-                    .bind_pipeline_graphics(rcx.pipeline.clone())
-                    .unwrap()
-                    .bind_vertex_buffers(0, self.vertex_memory_mng.device_local_buffer.clone())
-                    .unwrap();
-
-                unsafe { builder.draw(self.vertex_memory_mng.size(), 1, 0, 0) }.unwrap();
-
-                builder.end_render_pass(Default::default()).unwrap();
-
-                let command_buffer = builder.build().unwrap();
-                let future = rcx
-                    .previous_frame_end
-                    .take()
-                    .unwrap()
-                    .join(acquire_future)
-                    .then_execute(self.queue.clone(), command_buffer)
-                    .unwrap()
-                    .then_swapchain_present(
-                        self.queue.clone(),
-                        SwapchainPresentInfo::swapchain_image_index(
-                            rcx.swapchain.clone(),
-                            image_index,
-                        ),
-                    )
-                    .then_signal_fence_and_flush();
+        match self.panel_action.take() {
+            Some(PanelAction::Save) => self.quick_save(),
+            Some(PanelAction::Load) => self.quick_load(),
+            None => {}
+        }
 
-                rcx.previous_frame_end = match future.map_err(Validated::unwrap) {
-                    // Success, store result into vector.
-                    Ok(future) => Some(future.boxed()),
-                    // Unknown failure.
-                    Err(e) => panic!("failed to flush future: {e}"),
-                };
+        match self.error.take() {
+            Some(Error::DeviceLost) => {
+                warn!("the GPU was lost, recreating everything on it");
+                if let Err(e) = self.recover_device(event_loop) {
+                    self.error = Some(e);
+                    event_loop.exit();
+                }
             }
-            _ => {}
+            Some(e) => {
+                self.error = Some(e);
+                event_loop.exit();
+            }
+            None => {}
+        }
+
+        if let Some(rcx) = &self.rcx {
+            rcx.window.request_redraw();
         }
     }
+}
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        let rcx = self.rcx.as_mut().unwrap();
-        rcx.window.request_redraw();
+// Everything living on the device, created anew when it is lost.
+struct Gpu {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    frame_buffer_allocator: SubbufferAllocator,
+    vertex_memory_mng: DynMemoryManager,
+    spatial_hash: SpatialHash,
+    compute_pipeline: Arc<ComputePipeline>,
+    chain_reaction: ChainReaction,
+}
+
+impl Gpu {
+    // Without a `display`, the device only simulates and never presents.
+    fn new(
+        instance: &Arc<Instance>,
+        display: Option<&dyn HasDisplayHandle>,
+        device_selector: Option<&DeviceSelector>,
+        max_particles: u32,
+    ) -> Result<Self, Error> {
+        let (physical_device, queue_family_index) =
+            device::select(instance, display, device_selector)?;
+
+        info!(
+            device = %physical_device.properties().device_name,
+            device_type = ?physical_device.properties().device_type,
+            "using device",
+        );
+
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                enabled_extensions: device::required_extensions(display.is_some()),
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )?;
+
+        let queue = queues.next().unwrap();
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device.clone(),
+            Default::default(),
+        ));
+        let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
+            device.clone(),
+            Default::default(),
+        ));
+        // Simulation parameters, attractors, wind and obstacles are written to fresh buffers every
+        // frame.
+        let frame_buffer_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::UNIFORM_BUFFER
+                    | BufferUsage::STORAGE_BUFFER
+                    | BufferUsage::VERTEX_BUFFER,
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        );
+
+        // Create a compute-pipeline for applying the compute shader to vertices.
+        let compute_pipeline = new_compute_pipeline(
+            &device,
+            cs::load(device.clone())?.entry_point("main").unwrap(),
+        )?;
+        let compact_pipeline = new_compute_pipeline(
+            &device,
+            compact_cs::load(device.clone())?
+                .entry_point("main")
+                .unwrap(),
+        )?;
+        let neighbour_pipeline = new_compute_pipeline(
+            &device,
+            neighbour_cs::load(device.clone())?
+                .entry_point("main")
+                .unwrap(),
+        )?;
+
+        // Apply scoped logic to create `DeviceLocalBuffer` initialized with vertex data.
+        let vertex_memory_mng = DynMemoryManager::new(
+            memory_allocator.clone(),
+            descriptor_set_allocator.clone(),
+            command_buffer_allocator.clone(),
+            queue.clone(),
+            compute_pipeline.clone(),
+            compact_pipeline,
+            max_particles,
+        )?;
+
+        let spatial_hash = SpatialHash::new(
+            &memory_allocator,
+            descriptor_set_allocator.clone(),
+            neighbour_pipeline,
+            &vertex_memory_mng.device_local_buffer,
+            &vertex_memory_mng.hash_buffers,
+        )?;
+
+        let chain_reaction = ChainReaction::new(
+            &memory_allocator,
+            &descriptor_set_allocator,
+            &compute_pipeline,
+        )?;
+
+        Ok(Self {
+            device,
+            queue,
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            frame_buffer_allocator,
+            vertex_memory_mng,
+            spatial_hash,
+            compute_pipeline,
+            chain_reaction,
+        })
     }
 }
 
@@ -454,75 +1702,102 @@ fn window_size_dependent_setup(
     window_size: PhysicalSize<u32>,
     images: &[Arc<Image>],
     render_pass: &Arc<RenderPass>,
-    memory_allocator: &Arc<StandardMemoryAllocator>,
+    scene_pass: &Arc<RenderPass>,
     vs: &EntryPoint,
     fs: &EntryPoint,
-) -> (Vec<Arc<Framebuffer>>, Arc<GraphicsPipeline>) {
-    let device = memory_allocator.device();
-
+    solid_vs: &EntryPoint,
+) -> Result<
+    (
+        Vec<Arc<Framebuffer>>,
+        Arc<GraphicsPipeline>,
+        Arc<GraphicsPipeline>,
+    ),
+    Error,
+> {
     let framebuffers = images
         .iter()
-        .map(|img| {
-            let view = ImageView::new_default(img.clone()).unwrap();
-            Framebuffer::new(
+        .map(|img| -> Result<_, Error> {
+            let view = ImageView::new_default(img.clone())?;
+            let framebuffer = Framebuffer::new(
                 render_pass.clone(),
                 FramebufferCreateInfo {
                     attachments: vec![view],
                     ..Default::default()
                 },
-            )
-            .unwrap()
+            )?;
+            Ok(framebuffer)
         })
-        .collect();
-
-    // Create a basic graphics pipeline for rendering particles.
-    let pipeline = {
-        let vertex_input_state = MyVertex::per_vertex().definition(vs).unwrap();
-        let stages = [
-            PipelineShaderStageCreateInfo::new(vs.clone()),
-            PipelineShaderStageCreateInfo::new(fs.clone()),
-        ];
-        let layout = PipelineLayout::new(
-            device.clone(),
-            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                .into_pipeline_layout_create_info(device.clone())
-                .unwrap(),
-        )
-        .unwrap();
-        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        .collect::<Result<_, _>>()?;
 
-        GraphicsPipeline::new(
-            device.clone(),
-            None,
-            GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
-                vertex_input_state: Some(vertex_input_state),
-                // Vertices will be rendered as a list of points.
-                input_assembly_state: Some(InputAssemblyState {
-                    topology: PrimitiveTopology::PointList,
-                    ..Default::default()
-                }),
-                viewport_state: Some(ViewportState {
-                    viewports: [Viewport {
-                        offset: [0.0, 0.0],
-                        extent: window_size.into(),
-                        depth_range: 0.0..=1.0,
-                    }]
-                    .into_iter()
-                    .collect(),
-                    ..Default::default()
-                }),
-                rasterization_state: Some(RasterizationState::default()),
-                multisample_state: Some(MultisampleState::default()),
-                color_blend_state: Some(ColorBlendState::with_attachment_states(
-                    subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
-                )),
-                subpass: Some(subpass.into()),
-                ..GraphicsPipelineCreateInfo::layout(layout)
-            },
-        )
-        .unwrap()
-    };
-    (framebuffers, pipeline)
+    let (pipeline, solid_pipeline) =
+        new_scene_pipelines(window_size, scene_pass, vs, fs, solid_vs)?;
+    Ok((framebuffers, pipeline, solid_pipeline))
+}
+
+// Particles are rendered as a list of points, obstacles as two triangles per cell.
+fn new_scene_pipelines(
+    window_size: PhysicalSize<u32>,
+    scene_pass: &Arc<RenderPass>,
+    vs: &EntryPoint,
+    fs: &EntryPoint,
+    solid_vs: &EntryPoint,
+) -> Result<(Arc<GraphicsPipeline>, Arc<GraphicsPipeline>), Error> {
+    let pipeline = new_graphics_pipeline(
+        window_size,
+        scene_pass,
+        scene_pass.device(),
+        [vs, fs],
+        MyVertex::per_vertex().definition(vs)?,
+        PrimitiveTopology::PointList,
+        None,
+    )?;
+    let solid_pipeline = new_graphics_pipeline(
+        window_size,
+        scene_pass,
+        scene_pass.device(),
+        [solid_vs, fs],
+        SolidVertex::per_vertex().definition(solid_vs)?,
+        PrimitiveTopology::TriangleList,
+        None,
+    )?;
+    Ok((pipeline, solid_pipeline))
+}
+
+// Rebuilds every graphics pipeline of `rcx` using `shaders`. They are all built before any is
+// replaced, so on failure `rcx` is left as it was.
+#[cfg(feature = "shader-reload")]
+fn set_graphics_shaders(rcx: &mut RenderContext, shaders: &Shaders) -> Result<(), Error> {
+    let window_size = rcx.swapchain.image_extent().into();
+    let (pipeline, solid_pipeline) = new_scene_pipelines(
+        window_size,
+        rcx.post.scene_pass(),
+        &shaders.vs,
+        &shaders.fs,
+        &shaders.solid_vs,
+    )?;
+    let mut trails = rcx.trails.clone();
+    trails.set_shaders(window_size, &shaders.vs, &shaders.fs)?;
+    let mut debug = rcx.debug.clone();
+    debug.set_fs(rcx.post.scene_pass(), window_size, &shaders.fs)?;
+
+    rcx.pipeline = pipeline;
+    rcx.solid_pipeline = solid_pipeline;
+    rcx.trails = trails;
+    rcx.debug = debug;
+    rcx.vs = shaders.vs.clone();
+    rcx.fs = shaders.fs.clone();
+    rcx.solid_vs = shaders.solid_vs.clone();
+    Ok(())
+}
+
+fn cycle_edge(name: &str, edge: &mut Boundary) {
+    *edge = edge.cycle();
+    info!("{name} edge is now {edge:?}");
+}
+
+fn wall_vertices(walls: &ObstacleMask) -> Vec<SolidVertex> {
+    walls
+        .cells()
+        .flat_map(|(x, y)| SolidVertex::cell_quad(x, y, WALL_COLOR))
+        .collect()
 }
@@ -0,0 +1,57 @@
+use serde::Deserialize;
+
+/// Upper bound on the number of attractors, since every particle iterates over all of them.
+pub const MAX_ATTRACTORS: usize = 64;
+
+/// A point that pulls particles towards it, or pushes them away if `strength` is negative.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Attractor {
+    pub pos: [f32; 2],
+    pub strength: f32,
+    /// Exponent of the distance the pull falls off with, 2.0 being an inverse-square law.
+    pub falloff: f32,
+}
+
+impl Default for Attractor {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            strength: 2.0,
+            falloff: 1.0,
+        }
+    }
+}
+
+impl Attractor {
+    pub fn new(pos: [f32; 2]) -> Self {
+        Self {
+            pos,
+            ..Default::default()
+        }
+    }
+
+    /// An attractor with the default strength, but pushing particles away.
+    pub fn repeller(pos: [f32; 2]) -> Self {
+        let attractor = Self::new(pos);
+        Self {
+            strength: -attractor.strength,
+            ..attractor
+        }
+    }
+}
+
+/// Removes the attractor closest to `pos`, if one lies within `radius`.
+pub fn remove_nearest(attractors: &mut Vec<Attractor>, pos: [f32; 2], radius: f32) {
+    let distance = |a: &Attractor| (a.pos[0] - pos[0]).hypot(a.pos[1] - pos[1]);
+    let nearest = attractors
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| distance(a) <= radius)
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map(|(i, _)| i);
+
+    if let Some(i) = nearest {
+        attractors.swap_remove(i);
+    }
+}
@@ -0,0 +1,96 @@
+use serde::Deserialize;
+
+// Mode ids as understood by the compute shader. Keep in sync with the constants in `cs`.
+const MODE_SOLID: u32 = 0;
+const MODE_BOUNCY: u32 = 1;
+const MODE_WRAP: u32 = 2;
+const MODE_OPEN: u32 = 3;
+
+// Restitution used when cycling an edge into `Bouncy` at runtime.
+const DEFAULT_RESTITUTION: f32 = 0.95;
+
+/// What happens to a particle that crosses one edge of the world.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "mode", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Boundary {
+    /// The particle stops at the edge.
+    Solid,
+    /// The particle is reflected, keeping `restitution` of its speed.
+    Bouncy { restitution: f32 },
+    /// The particle re-enters from the opposite edge.
+    Wrap,
+    /// The particle is deleted.
+    Open,
+}
+
+impl Boundary {
+    /// Returns the next mode in the runtime cycle: solid, bouncy, wrap, open.
+    pub fn cycle(self) -> Self {
+        match self {
+            Boundary::Solid => Boundary::Bouncy {
+                restitution: DEFAULT_RESTITUTION,
+            },
+            Boundary::Bouncy { .. } => Boundary::Wrap,
+            Boundary::Wrap => Boundary::Open,
+            Boundary::Open => Boundary::Solid,
+        }
+    }
+
+    fn mode(self) -> u32 {
+        match self {
+            Boundary::Solid => MODE_SOLID,
+            Boundary::Bouncy { .. } => MODE_BOUNCY,
+            Boundary::Wrap => MODE_WRAP,
+            Boundary::Open => MODE_OPEN,
+        }
+    }
+
+    fn restitution(self) -> f32 {
+        match self {
+            Boundary::Bouncy { restitution } => restitution,
+            _ => 0.0,
+        }
+    }
+}
+
+/// The boundary behaviour of each edge of the world. `bottom` is the floor the default gravity
+/// pulls towards.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Boundaries {
+    pub left: Boundary,
+    pub right: Boundary,
+    pub top: Boundary,
+    pub bottom: Boundary,
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        Self {
+            left: Boundary::Bouncy { restitution: 0.95 },
+            right: Boundary::Bouncy { restitution: 0.95 },
+            top: Boundary::Bouncy { restitution: 0.95 },
+            bottom: Boundary::Bouncy { restitution: 0.7 },
+        }
+    }
+}
+
+impl Boundaries {
+    /// Whether particles can leave the world through any edge.
+    pub fn has_open(&self) -> bool {
+        self.edges().contains(&Boundary::Open)
+    }
+
+    // Both arrays are ordered left, right, top, bottom, matching `SimParams` in `cs`.
+    pub(crate) fn modes(&self) -> [u32; 4] {
+        self.edges().map(Boundary::mode)
+    }
+
+    pub(crate) fn restitutions(&self) -> [f32; 4] {
+        self.edges().map(Boundary::restitution)
+    }
+
+    fn edges(&self) -> [Boundary; 4] {
+        [self.left, self.right, self.top, self.bottom]
+    }
+}
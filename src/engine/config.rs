@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap, convert::Infallible, error::Error, fmt, fs, io::ErrorKind, path::Path,
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::error;
+use vulkano::{device::physical::PhysicalDeviceType, swapchain};
+use winit::keyboard::KeyCode;
+
+use super::{
+    attractor::Attractor,
+    boundary::Boundaries,
+    emitter::{Emitter, Sink},
+    explosion::ExplosionSettings,
+    keys::Action,
+    material::{Material, StyleOverride},
+    obstacles::WallBrush,
+    physics::PhysicsParams,
+    render::RenderSettings,
+    rigid_body::BodySettings,
+    wind::WindBrush,
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "sand.toml";
+
+// About 92 MB of GPU memory: 84 bytes per particle for the particle, its copy in the scratch
+// buffer compaction needs and its entries in the buffers `SpatialHash` sorts into, plus the 8 MB
+// hash table.
+const DEFAULT_MAX_PARTICLES: u32 = 1_000_000;
+
+/// Settings read from the TOML config file. Every section is optional and falls back to its
+/// defaults. While running, a section changed in the file replaces the current settings of that
+/// section right away, except for `limits`, `gpu`, `seed` and the attractors, emitters and sinks
+/// placed at startup.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub boundaries: Boundaries,
+    pub physics: PhysicsParams,
+    /// Attractors placed in the world at startup.
+    pub attractors: Vec<Attractor>,
+    pub wind: WindBrush,
+    pub bodies: BodySettings,
+    pub explosions: ExplosionSettings,
+    pub walls: WallBrush,
+    /// Emitters and sinks placed in the world at startup.
+    pub emitters: Vec<Emitter>,
+    pub sinks: Vec<Sink>,
+    /// Changes to how each material is drawn, keyed by material name.
+    pub materials: HashMap<Material, StyleOverride>,
+    pub render: RenderSettings,
+    /// Keys moved to other actions than by default, keyed by action name.
+    pub keys: HashMap<Action, KeyCode>,
+    pub limits: Limits,
+    pub window: WindowSettings,
+    pub gpu: GpuSettings,
+    /// Seed of all randomness, so runs can be repeated.
+    pub seed: u32,
+}
+
+/// How far the simulation may grow.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Spawning stops once there are this many particles.
+    pub max_particles: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_particles: DEFAULT_MAX_PARTICLES,
+        }
+    }
+}
+
+/// The window the world is drawn in.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    pub width: u32,
+    pub height: u32,
+    pub present_mode: PresentMode,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 1024,
+            height: 768,
+            present_mode: PresentMode::Fifo,
+        }
+    }
+}
+
+/// How finished frames are shown. Devices that don't support the chosen mode fall back to `Fifo`,
+/// which all of them do.
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    /// Wait for the display to refresh, never tearing.
+    Fifo,
+    /// Wait for the display to refresh, unless the frame is late.
+    FifoRelaxed,
+    /// Replace the waiting frame with newer ones, never tearing.
+    Mailbox,
+    /// Show frames right away, tearing.
+    Immediate,
+}
+
+impl From<PresentMode> for swapchain::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => Self::Fifo,
+            PresentMode::FifoRelaxed => Self::FifoRelaxed,
+            PresentMode::Mailbox => Self::Mailbox,
+            PresentMode::Immediate => Self::Immediate,
+        }
+    }
+}
+
+/// Which GPU runs the simulation.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuSettings {
+    /// By default the fastest kind of device that can run the simulation is picked.
+    pub device: Option<DeviceSelector>,
+}
+
+/// Picks a device by its index as printed by `list-devices`, its type, or part of its name. Of
+/// several devices of the type or with the name, the first one that can run the simulation is
+/// picked.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "RawDeviceSelector")]
+pub enum DeviceSelector {
+    Index(usize),
+    Type(DeviceType),
+    /// Matched ignoring case, such as `nvidia` or `llvmpipe`.
+    Name(String),
+}
+
+impl FromStr for DeviceSelector {
+    type Err = Infallible;
+
+    // Anything that isn't an index or a type is part of a name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(index) = s.parse() {
+            return Ok(Self::Index(index));
+        }
+        Ok(DeviceType::from_str(s, true).map_or_else(|_| Self::Name(s.to_owned()), Self::Type))
+    }
+}
+
+// The config file gives either an index or a string, parsed the same way as `--device`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDeviceSelector {
+    Index(usize),
+    Text(String),
+}
+
+impl From<RawDeviceSelector> for DeviceSelector {
+    fn from(raw: RawDeviceSelector) -> Self {
+        match raw {
+            RawDeviceSelector::Index(index) => Self::Index(index),
+            RawDeviceSelector::Text(text) => match text.parse() {
+                Ok(selector) => selector,
+                Err(e) => match e {},
+            },
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{index}"),
+            Self::Type(device_type) => {
+                write!(f, "{}", device_type.to_possible_value().unwrap().get_name())
+            }
+            Self::Name(name) => write!(f, "{name:?}"),
+        }
+    }
+}
+
+/// The kinds of devices. A CPU device is a software rasteriser, such as llvmpipe.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    DiscreteGpu,
+    IntegratedGpu,
+    VirtualGpu,
+    Cpu,
+}
+
+impl From<DeviceType> for PhysicalDeviceType {
+    fn from(device_type: DeviceType) -> Self {
+        match device_type {
+            DeviceType::DiscreteGpu => Self::DiscreteGpu,
+            DeviceType::IntegratedGpu => Self::IntegratedGpu,
+            DeviceType::VirtualGpu => Self::VirtualGpu,
+            DeviceType::Cpu => Self::Cpu,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    // A missing file is not an error, the defaults are simply used. A broken one is reported.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match Self::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                let missing = e
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::NotFound);
+                if !missing {
+                    error!("failed to load {}: {e}, using defaults", path.display());
+                }
+                Self::default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{boundary::Boundary, material::Palette};
+
+    #[test]
+    fn parses_boundaries() {
+        let config: Config = toml::from_str(
+            r#"
+            [boundaries]
+            left = { mode = "wrap" }
+            right = { mode = "wrap" }
+            bottom = { mode = "bouncy", restitution = 0.5 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.boundaries.left, Boundary::Wrap);
+        assert_eq!(
+            config.boundaries.bottom,
+            Boundary::Bouncy { restitution: 0.5 }
+        );
+        // Unspecified edges keep their default.
+        assert_eq!(config.boundaries.top, Boundaries::default().top);
+    }
+
+    #[test]
+    fn parses_partial_physics() {
+        let config: Config = toml::from_str("physics = { gravity = [1.0, 0.0] }").unwrap();
+
+        assert_eq!(config.physics.gravity, [1.0, 0.0]);
+        assert_eq!(config.physics.max_speed, PhysicsParams::default().max_speed);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(toml::from_str::<Config>("physics = { gravty = [1.0, 0.0] }").is_err());
+        assert!(toml::from_str::<Config>("[phyiscs]").is_err());
+        assert!(toml::from_str::<Config>(
+            r#"boundaries = { left = { mode = "bouncy", restitution = 0.5, friction = 0.1 } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_attractors() {
+        let config: Config = toml::from_str(
+            r#"
+            [[attractors]]
+            pos = [0.5, -0.5]
+            falloff = 2.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.attractors,
+            [Attractor {
+                falloff: 2.0,
+                ..Attractor::new([0.5, -0.5])
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_material_styles() {
+        let config: Config = toml::from_str(
+            r#"
+            [materials.sand]
+            color = [1.0, 0.0, 0.0]
+            "#,
+        )
+        .unwrap();
+
+        let palette = Palette::new(&config.materials);
+        let sand = palette.styles()[Material::Sand.id() as usize];
+        assert_eq!(sand.color, [1.0, 0.0, 0.0]);
+        assert_eq!(sand.variation, Material::Sand.default_style().variation);
+    }
+
+    #[test]
+    fn parses_limits() {
+        let config: Config = toml::from_str("limits = { max_particles = 5000 }").unwrap();
+        assert_eq!(config.limits.max_particles, 5000);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.limits, Limits::default());
+    }
+
+    #[test]
+    fn parses_keys() {
+        let config: Config = toml::from_str(
+            r#"
+            [keys]
+            pause = "KeyP"
+            wall-tool = "KeyW"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.keys[&Action::Pause], KeyCode::KeyP);
+        assert_eq!(config.keys[&Action::WallTool], KeyCode::KeyW);
+        assert!(toml::from_str::<Config>("keys = { pause = \"NotAKey\" }").is_err());
+    }
+
+    #[test]
+    fn parses_window() {
+        let config: Config = toml::from_str(
+            r#"
+            [window]
+            width = 640
+            present_mode = "fifo-relaxed"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.window.width, 640);
+        assert_eq!(config.window.height, WindowSettings::default().height);
+        assert_eq!(config.window.present_mode, PresentMode::FifoRelaxed);
+    }
+
+    #[test]
+    fn parses_device() {
+        let device = |text| {
+            let config: Config = toml::from_str(text).unwrap();
+            config.gpu.device.unwrap()
+        };
+
+        assert_eq!(device("gpu = { device = 1 }"), DeviceSelector::Index(1));
+        assert_eq!(
+            device(r#"gpu = { device = "1" }"#),
+            DeviceSelector::Index(1)
+        );
+        assert_eq!(
+            device(r#"gpu = { device = "CPU" }"#),
+            DeviceSelector::Type(DeviceType::Cpu)
+        );
+        assert_eq!(
+            device(r#"gpu = { device = "GeForce" }"#),
+            DeviceSelector::Name("GeForce".into())
+        );
+        assert_eq!(
+            "Discrete-GPU".parse(),
+            Ok(DeviceSelector::Type(DeviceType::DiscreteGpu))
+        );
+        assert_eq!("radeon".parse(), Ok(DeviceSelector::Name("radeon".into())));
+    }
+}
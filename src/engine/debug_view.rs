@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::DeviceOwned,
+    pipeline::{
+        graphics::{
+            input_assembly::PrimitiveTopology,
+            vertex_input::{Vertex, VertexDefinition},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::RenderPass,
+    shader::EntryPoint,
+};
+use winit::dpi::PhysicalSize;
+
+use super::{
+    error::Error,
+    pipeline::new_graphics_pipeline,
+    shaders::{cs, debug_vs},
+    MyVertex,
+};
+
+/// Draws the particles with `debug_vs`, in place of the normal render mode while a debug view
+/// is selected.
+#[derive(Clone)]
+pub struct DebugRenderer {
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    debug_vs: EntryPoint,
+    fs: EntryPoint,
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+impl DebugRenderer {
+    /// `fs` is the fragment shader particles are drawn with.
+    pub fn new(
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        scene_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+        fs: &EntryPoint,
+    ) -> Result<Self, Error> {
+        let debug_vs = debug_vs::load(scene_pass.device().clone())?
+            .entry_point("main")
+            .unwrap();
+        let pipeline = new_pipeline(scene_pass, window_size, &debug_vs, fs)?;
+
+        Ok(Self {
+            descriptor_set_allocator,
+            debug_vs,
+            fs: fs.clone(),
+            pipeline,
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        scene_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<(), Error> {
+        self.pipeline = new_pipeline(scene_pass, window_size, &self.debug_vs, &self.fs)?;
+        Ok(())
+    }
+
+    /// Replaces the fragment shader particles are drawn with, rebuilding the pipeline. On
+    /// failure the current one is kept.
+    #[cfg(feature = "shader-reload")]
+    pub fn set_fs(
+        &mut self,
+        scene_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+        fs: &EntryPoint,
+    ) -> Result<(), Error> {
+        self.pipeline = new_pipeline(scene_pass, window_size, &self.debug_vs, fs)?;
+        self.fs = fs.clone();
+        Ok(())
+    }
+
+    /// Records drawing the particles colored by the view in `push`. Must be recorded inside the
+    /// scene render pass.
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertices: &Subbuffer<[MyVertex]>,
+        sim_params: Subbuffer<cs::SimParams>,
+        densities: Subbuffer<[f32]>,
+        push: debug_vs::PushConstants,
+    ) -> Result<(), Error> {
+        let particle_count = push.particle_count;
+        let layout = self.pipeline.layout();
+        let descriptor_set = DescriptorSet::new(
+            self.descriptor_set_allocator.clone(),
+            layout.set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, sim_params),
+                WriteDescriptorSet::buffer(1, densities),
+            ],
+            [],
+        )?;
+
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                descriptor_set,
+            )?
+            .push_constants(layout.clone(), 0, push)?
+            .bind_vertex_buffers(0, vertices.clone())?;
+        unsafe { builder.draw(particle_count, 1, 0, 0) }?;
+        Ok(())
+    }
+}
+
+fn new_pipeline(
+    scene_pass: &Arc<RenderPass>,
+    window_size: PhysicalSize<u32>,
+    debug_vs: &EntryPoint,
+    fs: &EntryPoint,
+) -> Result<Arc<GraphicsPipeline>, Error> {
+    new_graphics_pipeline(
+        window_size,
+        scene_pass,
+        scene_pass.device(),
+        [debug_vs, fs],
+        MyVertex::per_vertex().definition(debug_vs)?,
+        PrimitiveTopology::PointList,
+        None,
+    )
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::{
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        DeviceExtensions, QueueFlags,
+    },
+    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
+    swapchain::Surface,
+    VulkanLibrary,
+};
+use winit::raw_window_handle::HasDisplayHandle;
+
+use super::{config::DeviceSelector, Error};
+
+/// The simulation shaders run in workgroups of this many particles.
+pub const WORKGROUP_SIZE: u32 = 1024;
+
+/// Creates the Vulkan instance. Given the `display` windows are shown on, it can draw to them.
+pub fn new_instance(display: Option<&dyn HasDisplayHandle>) -> Result<Arc<Instance>, Error> {
+    let library = VulkanLibrary::new()?;
+    let enabled_extensions = match display {
+        Some(display) => {
+            Surface::required_extensions(&display).map_err(|e| Error::Swapchain(Box::new(e)))?
+        }
+        None => Default::default(),
+    };
+    Ok(Instance::new(
+        library,
+        InstanceCreateInfo {
+            enabled_extensions,
+            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+            ..Default::default()
+        },
+    )?)
+}
+
+/// Every device, in the order `select` numbers them.
+pub fn list(instance: &Arc<Instance>) -> Result<Vec<Arc<PhysicalDevice>>, Error> {
+    Ok(instance.enumerate_physical_devices()?.collect())
+}
+
+/// Extensions the device is created with. Presenting needs a swapchain.
+pub fn required_extensions(present: bool) -> DeviceExtensions {
+    DeviceExtensions {
+        khr_swapchain: present,
+        ..DeviceExtensions::empty()
+    }
+}
+
+/// Picks the device to run on, along with the queue family the simulation is submitted to. Given
+/// `display`, the queue must also present to windows on it. Without a `selector`, the fastest kind
+/// of device that can run the simulation is used.
+pub fn select(
+    instance: &Arc<Instance>,
+    display: Option<&dyn HasDisplayHandle>,
+    selector: Option<&DeviceSelector>,
+) -> Result<(Arc<PhysicalDevice>, u32), Error> {
+    let devices = list(instance)?;
+    if let Some(selector) = selector {
+        let matching: Vec<_> = devices
+            .iter()
+            .enumerate()
+            .filter(|&(i, p)| matches(selector, i, p))
+            .map(|(_, p)| p)
+            .collect();
+        return matching
+            .iter()
+            .find_map(|p| queue_family(p, display).map(|i| (Arc::clone(p), i)))
+            .ok_or_else(|| match matching.first() {
+                Some(p) => Error::UnsuitableDevice(p.properties().device_name.clone()),
+                None => Error::NoSuchDevice(selector.clone()),
+            });
+    }
+
+    devices
+        .into_iter()
+        .filter_map(|p| queue_family(&p, display).map(|i| (p, i)))
+        .min_by_key(|(p, _)| match p.properties().device_type {
+            PhysicalDeviceType::DiscreteGpu => 0,
+            PhysicalDeviceType::IntegratedGpu => 1,
+            PhysicalDeviceType::VirtualGpu => 2,
+            PhysicalDeviceType::Cpu => 3,
+            PhysicalDeviceType::Other => 4,
+            _ => 5,
+        })
+        .ok_or(Error::NoSuitableDevice)
+}
+
+/// Whether `device` can run the simulation without a window.
+pub fn can_simulate(device: &PhysicalDevice) -> bool {
+    queue_family(device, None).is_some()
+}
+
+// Whether the device at `index` in `list` is the one `selector` asks for.
+fn matches(selector: &DeviceSelector, index: usize, device: &PhysicalDevice) -> bool {
+    let properties = device.properties();
+    match selector {
+        DeviceSelector::Index(i) => index == *i,
+        DeviceSelector::Type(device_type) => {
+            properties.device_type == PhysicalDeviceType::from(*device_type)
+        }
+        DeviceSelector::Name(name) => properties
+            .device_name
+            .to_lowercase()
+            .contains(&name.to_lowercase()),
+    }
+}
+
+// The first queue family of `device` that can run the simulation, and present to `display` if
+// given. Without a window, only compute is needed. Devices that can't run workgroups of
+// `WORKGROUP_SIZE` have none.
+fn queue_family(device: &PhysicalDevice, display: Option<&dyn HasDisplayHandle>) -> Option<u32> {
+    let properties = device.properties();
+    if !device
+        .supported_extensions()
+        .contains(&required_extensions(display.is_some()))
+        || properties.max_compute_work_group_invocations < WORKGROUP_SIZE
+        || properties.max_compute_work_group_size[0] < WORKGROUP_SIZE
+    {
+        return None;
+    }
+    device
+        .queue_family_properties()
+        .iter()
+        .enumerate()
+        .position(|(i, q)| match display {
+            // A device that can't tell whether it presents is just passed over.
+            Some(display) => {
+                q.queue_flags.intersects(QueueFlags::GRAPHICS)
+                    && device
+                        .presentation_support(i as u32, &display)
+                        .unwrap_or(false)
+            }
+            None => q.queue_flags.intersects(QueueFlags::COMPUTE),
+        })
+        .map(|i| i as u32)
+}
@@ -0,0 +1,148 @@
+use serde::Deserialize;
+
+use super::{hash, Material, MyVertex, Rng};
+
+/// Upper bound on the number of sinks, since every particle iterates over all of them.
+pub const MAX_SINKS: usize = 64;
+
+/// A source that spawns particles at a steady rate, like a tap or a hopper.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Emitter {
+    pub pos: [f32; 2],
+    pub material: Material,
+    /// Particles spawned per second.
+    pub rate: f32,
+    /// Velocity particles are spawned with, in normalized device coordinates per second.
+    pub velocity: [f32; 2],
+    /// Particles are spawned at random within this distance of `pos`.
+    pub spread: f32,
+    // Fraction of a particle left over from previous frames, and how many were spawned so far.
+    #[serde(skip)]
+    carry: f32,
+    #[serde(skip)]
+    emitted: u32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            material: Material::Sand,
+            rate: 300.0,
+            velocity: [0.0, 0.0],
+            spread: 0.01,
+            carry: 0.0,
+            emitted: 0,
+        }
+    }
+}
+
+impl Emitter {
+    pub fn new(pos: [f32; 2], material: Material) -> Self {
+        Self {
+            pos,
+            material,
+            ..Default::default()
+        }
+    }
+
+    /// Appends the particles spawned over `delta_time` to `out`.
+    pub fn emit(&mut self, delta_time: f32, rng: &mut Rng, out: &mut Vec<MyVertex>) {
+        let due = self.carry + self.rate * delta_time;
+        let count = due.floor();
+        self.carry = due - count;
+
+        for _ in 0..count as u32 {
+            let h = rng.hash(self.emitted ^ hash(self.pos[0].to_bits() ^ self.pos[1].to_bits()));
+            self.emitted = self.emitted.wrapping_add(1);
+
+            // A random point in the square around `pos`, from the two halves of the hash.
+            let jitter = |bits: u32| ((bits & 0xffff) as f32 / 65535.0 * 2.0 - 1.0) * self.spread;
+            let pos = [self.pos[0] + jitter(h), self.pos[1] + jitter(h >> 16)];
+            out.push(MyVertex::new(pos, self.velocity, self.material, rng));
+        }
+    }
+}
+
+/// A drain that deletes every particle that comes within `radius` of it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sink {
+    pub pos: [f32; 2],
+    pub radius: f32,
+}
+
+impl Default for Sink {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            radius: 0.03,
+        }
+    }
+}
+
+impl Sink {
+    pub fn new(pos: [f32; 2]) -> Self {
+        Self {
+            pos,
+            ..Default::default()
+        }
+    }
+}
+
+/// Removes the emitter or sink closest to `pos`, if one lies within `radius`.
+pub fn remove_nearest(
+    emitters: &mut Vec<Emitter>,
+    sinks: &mut Vec<Sink>,
+    pos: [f32; 2],
+    radius: f32,
+) {
+    let distance = |p: [f32; 2]| (p[0] - pos[0]).hypot(p[1] - pos[1]);
+    let nearest = |positions: &mut dyn Iterator<Item = [f32; 2]>| {
+        positions
+            .map(distance)
+            .enumerate()
+            .filter(|&(_, d)| d <= radius)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    };
+    let emitter = nearest(&mut emitters.iter().map(|e| e.pos));
+    let sink = nearest(&mut sinks.iter().map(|s| s.pos));
+
+    match (emitter, sink) {
+        (Some((i, a)), Some((_, b))) if a <= b => {
+            emitters.swap_remove(i);
+        }
+        (Some((i, _)), None) => {
+            emitters.swap_remove(i);
+        }
+        (_, Some((i, _))) => {
+            sinks.swap_remove(i);
+        }
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emits_at_its_rate_across_frames() {
+        let mut emitter = Emitter {
+            rate: 90.0,
+            ..Emitter::new([0.5, 0.5], Material::Sand)
+        };
+        let (mut rng, mut out) = (Rng::default(), Vec::new());
+        for _ in 0..60 {
+            emitter.emit(1.0 / 60.0, &mut rng, &mut out);
+        }
+
+        // 1.5 particles per frame, carried over between frames.
+        assert!((89..=90).contains(&out.len()), "{}", out.len());
+        for p in &out {
+            assert!((p.pos[0] - 0.5).abs() <= emitter.spread);
+            assert!((p.pos[1] - 0.5).abs() <= emitter.spread);
+        }
+    }
+}
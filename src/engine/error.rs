@@ -0,0 +1,178 @@
+use std::{fmt, io, path::PathBuf};
+
+use vulkano::{
+    buffer::AllocateBufferError, command_buffer::CommandBufferExecError, image::AllocateImageError,
+    memory::allocator::MemoryAllocatorError, pipeline::layout::IntoPipelineLayoutCreateInfoError,
+    sync::HostAccessError, LoadingError, Validated, ValidationError, VulkanError,
+};
+use winit::error::{EventLoopError, OsError};
+
+use super::config::DeviceSelector;
+
+/// Everything that can go wrong setting up the engine or growing its buffers.
+#[derive(Debug)]
+pub enum Error {
+    /// No device can run the simulation, and present to the window if there is one.
+    NoSuitableDevice,
+    /// No device matches the one asked for.
+    NoSuchDevice(DeviceSelector),
+    /// The device asked for, named here, can't run the simulation.
+    UnsuitableDevice(String),
+    /// The device ran out of memory allocating the particle buffers.
+    OutOfMemory {
+        capacity: u32,
+        source: Validated<AllocateBufferError>,
+    },
+    /// The swapchain, or the surface it presents to, couldn't be created.
+    Swapchain(Box<dyn std::error::Error>),
+    Window(OsError),
+    EventLoop(EventLoopError),
+    /// The device stopped working, after a driver crash or reset. Everything on it is gone.
+    DeviceLost,
+    /// Any other failure of the Vulkan library or device.
+    Vulkan(Box<dyn std::error::Error>),
+    /// Writing a save file failed.
+    Save {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Reading a save file failed.
+    Load {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// A failure allocating the particle buffers for `capacity` particles, which is `OutOfMemory`
+    /// only if the device or host ran out of memory.
+    pub(crate) fn allocation(capacity: u32, e: Validated<AllocateBufferError>) -> Self {
+        match e {
+            Validated::Error(AllocateBufferError::AllocateMemory(
+                MemoryAllocatorError::AllocateDeviceMemory(Validated::Error(
+                    VulkanError::OutOfDeviceMemory | VulkanError::OutOfHostMemory,
+                )),
+            )) => Self::OutOfMemory {
+                capacity,
+                source: e,
+            },
+            e => Self::Vulkan(Box::new(e)),
+        }
+    }
+
+    /// A failure creating the swapchain, unless it is because the device was lost.
+    pub(crate) fn swapchain(e: Validated<VulkanError>) -> Self {
+        match e {
+            Validated::Error(VulkanError::DeviceLost) => Self::DeviceLost,
+            e => Self::Swapchain(Box::new(e)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuitableDevice => {
+                write!(
+                    f,
+                    "no GPU supports the Vulkan features the simulation needs"
+                )
+            }
+            Self::NoSuchDevice(selector) => write!(
+                f,
+                "there is no GPU matching {selector}, see `list-devices` for the ones there are"
+            ),
+            Self::UnsuitableDevice(name) => {
+                write!(
+                    f,
+                    "{name} doesn't support the Vulkan features the simulation needs"
+                )
+            }
+            Self::OutOfMemory { capacity, .. } => write!(
+                f,
+                "out of GPU memory growing the particle buffer to {capacity} particles"
+            ),
+            Self::Swapchain(e) => write!(f, "failed to create the swapchain: {e}"),
+            Self::Window(e) => write!(f, "failed to create the window: {e}"),
+            Self::EventLoop(e) => write!(f, "event loop failed: {e}"),
+            Self::DeviceLost => write!(f, "the GPU was lost"),
+            Self::Vulkan(e) => write!(f, "Vulkan error: {e}"),
+            Self::Save { path, source } => {
+                write!(f, "failed to save to {}: {source}", path.display())
+            }
+            Self::Load { path, source } => {
+                write!(f, "failed to load {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::NoSuitableDevice
+            | Self::NoSuchDevice(_)
+            | Self::UnsuitableDevice(_)
+            | Self::DeviceLost => None,
+            Self::OutOfMemory { source, .. } => Some(source),
+            Self::Swapchain(e) | Self::Vulkan(e) => Some(e.as_ref()),
+            Self::Window(e) => Some(e),
+            Self::EventLoop(e) => Some(e),
+            Self::Save { source, .. } | Self::Load { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<OsError> for Error {
+    fn from(e: OsError) -> Self {
+        Self::Window(e)
+    }
+}
+
+impl From<EventLoopError> for Error {
+    fn from(e: EventLoopError) -> Self {
+        Self::EventLoop(e)
+    }
+}
+
+impl From<VulkanError> for Error {
+    fn from(e: VulkanError) -> Self {
+        match e {
+            VulkanError::DeviceLost => Self::DeviceLost,
+            e => Self::Vulkan(Box::new(e)),
+        }
+    }
+}
+
+impl From<Validated<VulkanError>> for Error {
+    fn from(e: Validated<VulkanError>) -> Self {
+        match e {
+            Validated::Error(e) => e.into(),
+            e => Self::Vulkan(Box::new(e)),
+        }
+    }
+}
+
+// The errors of the Vulkan calls made while setting up, passed on with `?`.
+macro_rules! vulkan_errors {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Self {
+                    Self::Vulkan(Box::new(e))
+                }
+            }
+        )*
+    };
+}
+
+vulkan_errors!(
+    LoadingError,
+    Box<ValidationError>,
+    CommandBufferExecError,
+    MemoryAllocatorError,
+    Validated<AllocateBufferError>,
+    Validated<AllocateImageError>,
+    IntoPipelineLayoutCreateInfoError,
+    HostAccessError,
+);
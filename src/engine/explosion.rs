@@ -0,0 +1,230 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use serde::Deserialize;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{ComputePipeline, Pipeline},
+    DeviceSize,
+};
+
+use super::{error::Error, shaders::cs};
+
+/// Number of explosions detonating particles can set off per frame. Keep in sync with
+/// `MAX_CHAIN_EXPLOSIONS` in `cs`.
+const MAX_CHAIN_EXPLOSIONS: DeviceSize = 256;
+
+/// Size and force of explosions. Radii are in normalized device coordinates, strengths are the
+/// speed given to particles at the center, in normalized device coordinates per second.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExplosionSettings {
+    /// Explosions set off with the explosion tool.
+    pub radius: f32,
+    pub strength: f32,
+    /// Explosions of a single detonating explosive particle.
+    pub chain_radius: f32,
+    pub chain_strength: f32,
+}
+
+impl Default for ExplosionSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.15,
+            strength: 4.0,
+            chain_radius: 0.04,
+            chain_strength: 2.0,
+        }
+    }
+}
+
+impl ExplosionSettings {
+    /// An explosion of the explosion tool at `pos`, as uploaded to `cs`.
+    pub(crate) fn at(&self, pos: [f32; 2]) -> cs::Explosion {
+        cs::Explosion {
+            pos,
+            radius: self.radius,
+            strength: self.strength,
+        }
+    }
+}
+
+/// Explosions produced on the GPU. Particles detonating during a frame append their explosions
+/// to one list, which is applied on the next frame while the other list is filled. Each frame's
+/// list is also copied back, so the CPU can apply the explosions to what it simulates itself.
+pub struct ChainReaction {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    // The number of explosions in each list.
+    counts: Subbuffer<[u32]>,
+    lists: [Subbuffer<[cs::Explosion]>; 2],
+    // Set `i` reads list `i` and appends to the other one.
+    descriptor_sets: [Arc<DescriptorSet>; 2],
+    current: usize,
+    // Copies of the lists of frames that may still be running, oldest first, and copies that
+    // were read and can be reused.
+    in_flight: VecDeque<Readback>,
+    free: Vec<Readback>,
+}
+
+// The explosions set off on one frame, copied to memory the CPU can read.
+struct Readback {
+    count: Subbuffer<u32>,
+    explosions: Subbuffer<[cs::Explosion]>,
+}
+
+impl Readback {
+    fn new(memory_allocator: &Arc<StandardMemoryAllocator>) -> Result<Self, Error> {
+        let create_info = || BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        };
+        let allocation_info = || AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        };
+        Ok(Self {
+            count: Buffer::from_data(
+                memory_allocator.clone(),
+                create_info(),
+                allocation_info(),
+                0,
+            )?,
+            explosions: Buffer::new_slice(
+                memory_allocator.clone(),
+                create_info(),
+                allocation_info(),
+                MAX_CHAIN_EXPLOSIONS,
+            )?,
+        })
+    }
+}
+
+impl ChainReaction {
+    pub fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+        compute_pipeline: &Arc<ComputePipeline>,
+    ) -> Result<Self, Error> {
+        let counts = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER
+                    | BufferUsage::TRANSFER_SRC
+                    | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            [0u32, 0],
+        )?;
+        let new_list = || {
+            Buffer::new_slice::<cs::Explosion>(
+                memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+                MAX_CHAIN_EXPLOSIONS,
+            )
+        };
+        let lists = [new_list()?, new_list()?];
+
+        let new_set = |read: usize| {
+            let write = 1 - read;
+            let count = |i: usize| counts.clone().slice(i as DeviceSize..i as DeviceSize + 1);
+            DescriptorSet::new(
+                descriptor_set_allocator.clone(),
+                compute_pipeline.layout().set_layouts()[2].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, lists[read].clone()),
+                    WriteDescriptorSet::buffer(1, count(read)),
+                    WriteDescriptorSet::buffer(2, lists[write].clone()),
+                    WriteDescriptorSet::buffer(3, count(write)),
+                ],
+                [],
+            )
+        };
+        let descriptor_sets = [new_set(0)?, new_set(1)?];
+
+        Ok(Self {
+            memory_allocator: memory_allocator.clone(),
+            counts,
+            lists,
+            descriptor_sets,
+            current: 0,
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+        })
+    }
+
+    /// Empties the list this frame's explosions are appended to, and returns the set to bind as
+    /// set 2 of `cs` for this frame.
+    pub fn record_next(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<Arc<DescriptorSet>, Error> {
+        let write = (1 - self.current) as DeviceSize;
+        builder.fill_buffer(self.counts.clone().slice(write..write + 1), 0)?;
+
+        let set = self.descriptor_sets[self.current].clone();
+        self.current = 1 - self.current;
+        Ok(set)
+    }
+
+    /// Copies the explosions set off by the dispatch recorded after `record_next` back to the
+    /// CPU, to be returned by `finished_explosions` once the frame is done.
+    pub fn record_readback(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), Error> {
+        let readback = match self.free.pop() {
+            Some(readback) => readback,
+            None => Readback::new(&self.memory_allocator)?,
+        };
+        // Frames that are never submitted leave the copy empty rather than stale.
+        *readback.count.write()? = 0;
+
+        // `record_next` already made the list written this frame the current one.
+        let write = self.current as DeviceSize;
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.counts.clone().slice(write..write + 1),
+                readback.count.clone(),
+            ))?
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.lists[self.current].clone(),
+                readback.explosions.clone(),
+            ))?;
+        self.in_flight.push_back(readback);
+        Ok(())
+    }
+
+    /// Returns the explosions set off on the frames the GPU finished since the last call. Frames
+    /// still running are left for a later call rather than waited for.
+    pub fn finished_explosions(&mut self) -> Vec<cs::Explosion> {
+        let mut explosions = Vec::new();
+        while let Some(readback) = self.in_flight.front() {
+            // Reading fails while the frame copying into it still holds it.
+            let Ok(count) = readback.count.read() else {
+                break;
+            };
+            let count = (*count as DeviceSize).min(MAX_CHAIN_EXPLOSIONS) as usize;
+            explosions.extend_from_slice(&readback.explosions.read().unwrap()[..count]);
+            let readback = self.in_flight.pop_front().unwrap();
+            self.free.push(readback);
+        }
+        explosions
+    }
+}
@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{AutoCommandBufferBuilder, ClearColorImageInfo, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::DeviceOwned,
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{input_assembly::PrimitiveTopology, vertex_input::VertexInputState},
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::RenderPass,
+    shader::EntryPoint,
+};
+use winit::dpi::PhysicalSize;
+
+use super::{
+    error::Error,
+    pipeline::{new_compute_pipeline, new_graphics_pipeline},
+    shaders::{cs, grid_cs, grid_fs, quad_vs, vs},
+    MyVertex,
+};
+
+/// Draws the world for `RenderMode::Grid`. The particles are written into a storage image with
+/// one texel per cell, which is then stretched over the window with nearest-neighbour sampling,
+/// so drawing costs the same however many particles there are.
+pub struct GridRenderer {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    splat_pipeline: Arc<ComputePipeline>,
+    quad_vs: EntryPoint,
+    grid_fs: EntryPoint,
+    sampler: Arc<Sampler>,
+    pixel_scale: u32,
+    // The rest depends on the window size and pixel scale.
+    image: Arc<ImageView>,
+    // The size of the window in cells, and the part of the image it covers.
+    extent: [f32; 2],
+    uv_scale: [f32; 2],
+    draw_pipeline: Arc<GraphicsPipeline>,
+    draw_set: Arc<DescriptorSet>,
+}
+
+impl GridRenderer {
+    pub fn new(
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        render_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+        pixel_scale: u32,
+    ) -> Result<Self, Error> {
+        let device = memory_allocator.device().clone();
+        let splat_pipeline = new_compute_pipeline(
+            &device,
+            grid_cs::load(device.clone())?.entry_point("main").unwrap(),
+        )?;
+        let quad_vs = quad_vs::load(device.clone())?.entry_point("main").unwrap();
+        let grid_fs = grid_fs::load(device.clone())?.entry_point("main").unwrap();
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+
+        let (image, extent, uv_scale) =
+            new_grid_image(&memory_allocator, window_size, pixel_scale)?;
+        let draw_pipeline = new_draw_pipeline(render_pass, window_size, &quad_vs, &grid_fs)?;
+        let draw_set = new_draw_set(&descriptor_set_allocator, &draw_pipeline, &image, &sampler)?;
+
+        Ok(Self {
+            memory_allocator,
+            descriptor_set_allocator,
+            splat_pipeline,
+            quad_vs,
+            grid_fs,
+            sampler,
+            pixel_scale,
+            image,
+            extent,
+            uv_scale,
+            draw_pipeline,
+            draw_set,
+        })
+    }
+
+    pub fn pixel_scale(&self) -> u32 {
+        self.pixel_scale
+    }
+
+    /// Recreates the image and pipeline for a new window size or pixel scale.
+    pub fn resize(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+        pixel_scale: u32,
+    ) -> Result<(), Error> {
+        self.pixel_scale = pixel_scale;
+        (self.image, self.extent, self.uv_scale) =
+            new_grid_image(&self.memory_allocator, window_size, pixel_scale)?;
+        self.draw_pipeline =
+            new_draw_pipeline(render_pass, window_size, &self.quad_vs, &self.grid_fs)?;
+        self.draw_set = new_draw_set(
+            &self.descriptor_set_allocator,
+            &self.draw_pipeline,
+            &self.image,
+            &self.sampler,
+        )?;
+        Ok(())
+    }
+
+    /// Records clearing the grid and writing every particle into it. Must be recorded outside
+    /// of a render pass.
+    pub fn record_splat(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertices: &Subbuffer<[MyVertex]>,
+        particle_count: u32,
+        sim_params: Subbuffer<cs::SimParams>,
+        palette: Subbuffer<[vs::MaterialStyle]>,
+    ) -> Result<(), Error> {
+        builder.clear_color_image(ClearColorImageInfo {
+            clear_value: [0.0, 0.0, 0.0, 1.0].into(),
+            ..ClearColorImageInfo::image(self.image.image().clone())
+        })?;
+
+        if particle_count == 0 {
+            return Ok(());
+        }
+
+        let layout = self.splat_pipeline.layout();
+        let descriptor_set = DescriptorSet::new(
+            self.descriptor_set_allocator.clone(),
+            layout.set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, vertices.clone()),
+                WriteDescriptorSet::image_view(1, self.image.clone()),
+                WriteDescriptorSet::buffer(2, sim_params),
+                WriteDescriptorSet::buffer(3, palette),
+            ],
+            [],
+        )?;
+
+        builder
+            .push_constants(
+                layout.clone(),
+                0,
+                grid_cs::PushConstants {
+                    extent: self.extent,
+                    particle_count,
+                },
+            )?
+            .bind_pipeline_compute(self.splat_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                layout.clone(),
+                0,
+                descriptor_set,
+            )?;
+        unsafe { builder.dispatch([particle_count.div_ceil(1024), 1, 1]) }?;
+        Ok(())
+    }
+
+    /// Records drawing the grid over the whole window. Must be recorded inside the render pass
+    /// given to `new` or `resize`.
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), Error> {
+        let layout = self.draw_pipeline.layout();
+        builder
+            .bind_pipeline_graphics(self.draw_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.draw_set.clone(),
+            )?
+            .push_constants(
+                layout.clone(),
+                0,
+                grid_fs::PushConstants {
+                    uv_scale: self.uv_scale,
+                },
+            )?;
+        unsafe { builder.draw(4, 1, 0, 0) }?;
+        Ok(())
+    }
+}
+
+// The image holding one texel per cell, along with the size of the window in cells, and the part
+// of the image covered by the window.
+fn new_grid_image(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    window_size: PhysicalSize<u32>,
+    pixel_scale: u32,
+) -> Result<(Arc<ImageView>, [f32; 2], [f32; 2]), Error> {
+    let pixel_scale = pixel_scale.max(1);
+    let width = window_size.width.div_ceil(pixel_scale).max(1);
+    let height = window_size.height.div_ceil(pixel_scale).max(1);
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            // Wide enough for the emissive colors of glowing materials.
+            format: Format::R16G16B16A16_SFLOAT,
+            extent: [width, height, 1],
+            usage: ImageUsage::STORAGE | ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+
+    let extent = [
+        window_size.width as f32 / pixel_scale as f32,
+        window_size.height as f32 / pixel_scale as f32,
+    ];
+    let uv_scale = [extent[0] / width as f32, extent[1] / height as f32];
+    Ok((ImageView::new_default(image)?, extent, uv_scale))
+}
+
+fn new_draw_pipeline(
+    render_pass: &Arc<RenderPass>,
+    window_size: PhysicalSize<u32>,
+    quad_vs: &EntryPoint,
+    grid_fs: &EntryPoint,
+) -> Result<Arc<GraphicsPipeline>, Error> {
+    new_graphics_pipeline(
+        window_size,
+        render_pass,
+        render_pass.device(),
+        [quad_vs, grid_fs],
+        // The quad's corners are computed from the vertex index.
+        VertexInputState::new(),
+        PrimitiveTopology::TriangleStrip,
+        None,
+    )
+}
+
+fn new_draw_set(
+    descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    draw_pipeline: &Arc<GraphicsPipeline>,
+    image: &Arc<ImageView>,
+    sampler: &Arc<Sampler>,
+) -> Result<Arc<DescriptorSet>, Error> {
+    let set = DescriptorSet::new(
+        descriptor_set_allocator.clone(),
+        draw_pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            image.clone(),
+            sampler.clone(),
+        )],
+        [],
+    )?;
+    Ok(set)
+}
@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{allocator::SubbufferAllocator, BufferContents},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    device::DeviceOwned,
+    pipeline::{
+        graphics::{
+            color_blend::AttachmentBlend,
+            input_assembly::PrimitiveTopology,
+            vertex_input::{Vertex, VertexDefinition},
+        },
+        GraphicsPipeline,
+    },
+    render_pass::RenderPass,
+    shader::EntryPoint,
+    DeviceSize,
+};
+use winit::dpi::PhysicalSize;
+
+use super::{
+    error::Error,
+    font::{packed_glyph, GLYPH_HEIGHT, GLYPH_WIDTH},
+    pipeline::new_graphics_pipeline,
+    shaders::{hud_fs, hud_vs},
+};
+
+// Screen pixels per font pixel.
+const HUD_SCALE: f32 = 2.0;
+// Space between characters and between lines, in font pixels.
+const CHAR_SPACING: f32 = 1.0;
+const LINE_SPACING: f32 = 3.0;
+const CHAR_ADVANCE: f32 = (GLYPH_WIDTH as f32 + CHAR_SPACING) * HUD_SCALE;
+/// Height of a line of text, in screen pixels.
+pub const TEXT_HEIGHT: f32 = GLYPH_HEIGHT as f32 * HUD_SCALE;
+/// Space around the contents of a panel, in screen pixels.
+pub const MARGIN: f32 = 8.0;
+pub const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+// Frame rate and frame time are averaged over this many seconds, so they can be read.
+const STATS_INTERVAL: f32 = 0.5;
+
+#[derive(BufferContents, Vertex, Debug, Clone, Copy)]
+#[repr(C)]
+struct HudVertex {
+    #[format(R32G32_SFLOAT)]
+    pos: [f32; 2],
+    // Position within the glyph, in font pixels.
+    #[format(R32G32_SFLOAT)]
+    texel: [f32; 2],
+    // The pixels of the glyph, see `packed_glyph`.
+    #[format(R32G32_UINT)]
+    glyph: [u32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+/// The overlay drawn over the finished frame: the stats in the top left corner, and the panel of
+/// `Ui`. Everything is drawn with the same pipeline, text as a quad per character.
+pub struct Hud {
+    hud_vs: EntryPoint,
+    hud_fs: EntryPoint,
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+impl Hud {
+    pub fn new(
+        render_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<Self, Error> {
+        let device = render_pass.device();
+        let hud_vs = hud_vs::load(device.clone())?.entry_point("main").unwrap();
+        let hud_fs = hud_fs::load(device.clone())?.entry_point("main").unwrap();
+        let pipeline = new_pipeline(render_pass, window_size, &hud_vs, &hud_fs)?;
+
+        Ok(Self {
+            hud_vs,
+            hud_fs,
+            pipeline,
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<(), Error> {
+        self.pipeline = new_pipeline(render_pass, window_size, &self.hud_vs, &self.hud_fs)?;
+        Ok(())
+    }
+
+    /// Records drawing everything on `canvas`. Must be recorded inside the render pass given to
+    /// `new`.
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &SubbufferAllocator,
+        canvas: &Canvas,
+    ) -> Result<(), Error> {
+        if canvas.vertices.is_empty() {
+            return Ok(());
+        }
+
+        let buffer = allocator.allocate_slice(canvas.vertices.len() as DeviceSize)?;
+        buffer.write()?.copy_from_slice(&canvas.vertices);
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_vertex_buffers(0, buffer)?;
+        unsafe { builder.draw(canvas.vertices.len() as u32, 1, 0, 0) }?;
+        Ok(())
+    }
+}
+
+/// Rectangles and text to draw over the frame, positioned in screen pixels from the top left
+/// corner of the window.
+pub struct Canvas {
+    window_size: PhysicalSize<u32>,
+    vertices: Vec<HudVertex>,
+}
+
+impl Canvas {
+    pub fn new(window_size: PhysicalSize<u32>) -> Self {
+        Self {
+            window_size,
+            vertices: Vec::new(),
+        }
+    }
+
+    pub fn rect(&mut self, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+        let quad = self.quad(min, max, [u32::MAX; 2], color);
+        self.vertices.extend(quad);
+    }
+
+    /// Reserves a rectangle drawn below everything added after it, for backgrounds whose size
+    /// is only known once their contents are laid out. See `place_rect`.
+    pub fn reserve_rect(&mut self) -> usize {
+        let slot = self.vertices.len();
+        self.rect([0.0, 0.0], [0.0, 0.0], [0.0; 4]);
+        slot
+    }
+
+    pub fn place_rect(&mut self, slot: usize, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+        let quad = self.quad(min, max, [u32::MAX; 2], color);
+        self.vertices[slot..slot + quad.len()].copy_from_slice(&quad);
+    }
+
+    /// Draws a single line of text with its top left corner at `pos`.
+    pub fn text(&mut self, pos: [f32; 2], text: &str, color: [f32; 4]) {
+        for (column, c) in text.chars().enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            let x = pos[0] + column as f32 * CHAR_ADVANCE;
+            let max = [x + GLYPH_WIDTH as f32 * HUD_SCALE, pos[1] + TEXT_HEIGHT];
+            let quad = self.quad([x, pos[1]], max, packed_glyph(c), color);
+            self.vertices.extend(quad);
+        }
+    }
+
+    // Two triangles from the top left corner `min` to the bottom right corner `max`.
+    fn quad(
+        &self,
+        min: [f32; 2],
+        max: [f32; 2],
+        glyph: [u32; 2],
+        color: [f32; 4],
+    ) -> [HudVertex; 6] {
+        let [x0, y0] = self.to_ndc(min);
+        let [x1, y1] = self.to_ndc(max);
+        let (w, h) = (GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32);
+        [
+            ([x0, y0], [0.0, 0.0]),
+            ([x1, y0], [w, 0.0]),
+            ([x1, y1], [w, h]),
+            ([x0, y0], [0.0, 0.0]),
+            ([x1, y1], [w, h]),
+            ([x0, y1], [0.0, h]),
+        ]
+        .map(|(pos, texel)| HudVertex {
+            pos,
+            texel,
+            glyph,
+            color,
+        })
+    }
+
+    fn to_ndc(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        [
+            x / self.window_size.width as f32 * 2.0 - 1.0,
+            y / self.window_size.height as f32 * 2.0 - 1.0,
+        ]
+    }
+}
+
+/// Width of `text` drawn with `Canvas::text`, in screen pixels.
+pub fn text_width(text: &str) -> f32 {
+    (text.chars().count() as f32 * CHAR_ADVANCE - CHAR_SPACING * HUD_SCALE).max(0.0)
+}
+
+/// Draws `lines` over a panel in the top left corner.
+pub fn stats_panel(canvas: &mut Canvas, lines: &[String]) {
+    let width = lines
+        .iter()
+        .map(|line| text_width(line))
+        .fold(0.0, f32::max);
+    let line_height = TEXT_HEIGHT + LINE_SPACING * HUD_SCALE;
+    let height = lines.len() as f32 * line_height - LINE_SPACING * HUD_SCALE;
+    canvas.rect(
+        [0.0, 0.0],
+        [width + 2.0 * MARGIN, height + 2.0 * MARGIN],
+        PANEL_COLOR,
+    );
+
+    for (row, line) in lines.iter().enumerate() {
+        canvas.text(
+            [MARGIN, MARGIN + row as f32 * line_height],
+            line,
+            TEXT_COLOR,
+        );
+    }
+}
+
+/// Frame rate and frame time, averaged over a short interval.
+pub struct FrameStats {
+    frames: u32,
+    elapsed: f32,
+    fps: f32,
+    frame_time: f32,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            elapsed: 0.0,
+            fps: 0.0,
+            frame_time: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.frames += 1;
+        self.elapsed += delta_time;
+        if self.elapsed >= STATS_INTERVAL {
+            self.fps = self.frames as f32 / self.elapsed;
+            self.frame_time = self.elapsed / self.frames as f32;
+            self.frames = 0;
+            self.elapsed = 0.0;
+        }
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    /// Average frame time in milliseconds.
+    pub fn frame_time_ms(&self) -> f32 {
+        self.frame_time * 1000.0
+    }
+}
+
+fn new_pipeline(
+    render_pass: &Arc<RenderPass>,
+    window_size: PhysicalSize<u32>,
+    hud_vs: &EntryPoint,
+    hud_fs: &EntryPoint,
+) -> Result<Arc<GraphicsPipeline>, Error> {
+    new_graphics_pipeline(
+        window_size,
+        render_pass,
+        render_pass.device(),
+        [hud_vs, hud_fs],
+        HudVertex::per_vertex().definition(hud_vs)?,
+        PrimitiveTopology::TriangleList,
+        Some(AttachmentBlend::alpha()),
+    )
+}
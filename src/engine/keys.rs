@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use winit::keyboard::KeyCode;
+
+use super::tool::Tool;
+
+/// Something done with a key press. Keys holding shift do the opposite or a variation, as noted.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    SpawnTool,
+    AttractorTool,
+    WindTool,
+    BodyTool,
+    ExplosionTool,
+    WallTool,
+    EmitterTool,
+    /// Rotate the world by rotating gravity.
+    RotateLeft,
+    RotateRight,
+    /// Raise gravity, friction or max speed, or lower them with shift.
+    Gravity,
+    Friction,
+    MaxSpeed,
+    Save,
+    Load,
+    CycleMaterial,
+    /// Switch between drawing points and the grid.
+    RenderMode,
+    /// Change the size of grid cells.
+    PixelScale,
+    Pause,
+    Hud,
+    Panel,
+    DebugView,
+    /// Toggle trails, or switch how particles are blended over them with shift.
+    Trails,
+    Bloom,
+    Collisions,
+    Sph,
+    /// Cycle the mode of an edge.
+    LeftEdge,
+    RightEdge,
+    TopEdge,
+    BottomEdge,
+}
+
+impl Action {
+    /// The tool the action selects, if it is one of the tools.
+    pub fn tool(self) -> Option<Tool> {
+        match self {
+            Action::SpawnTool => Some(Tool::Spawn),
+            Action::AttractorTool => Some(Tool::Attractor),
+            Action::WindTool => Some(Tool::Wind),
+            Action::BodyTool => Some(Tool::Body),
+            Action::ExplosionTool => Some(Tool::Explosion),
+            Action::WallTool => Some(Tool::Wall),
+            Action::EmitterTool => Some(Tool::Emitter),
+            _ => None,
+        }
+    }
+}
+
+// Tools are on the number row, quick save and load on F5 and F9 and the edges on F1-F4.
+const DEFAULT_BINDINGS: [(Action, KeyCode); 29] = [
+    (Action::SpawnTool, KeyCode::Digit1),
+    (Action::AttractorTool, KeyCode::Digit2),
+    (Action::WindTool, KeyCode::Digit3),
+    (Action::BodyTool, KeyCode::Digit4),
+    (Action::ExplosionTool, KeyCode::Digit5),
+    (Action::WallTool, KeyCode::Digit6),
+    (Action::EmitterTool, KeyCode::Digit7),
+    (Action::RotateLeft, KeyCode::KeyQ),
+    (Action::RotateRight, KeyCode::KeyE),
+    (Action::Gravity, KeyCode::KeyG),
+    (Action::Friction, KeyCode::KeyF),
+    (Action::MaxSpeed, KeyCode::KeyM),
+    (Action::Save, KeyCode::F5),
+    (Action::Load, KeyCode::F9),
+    (Action::CycleMaterial, KeyCode::Tab),
+    (Action::RenderMode, KeyCode::KeyV),
+    (Action::PixelScale, KeyCode::KeyP),
+    (Action::Pause, KeyCode::Space),
+    (Action::Hud, KeyCode::KeyH),
+    (Action::Panel, KeyCode::KeyU),
+    (Action::DebugView, KeyCode::KeyO),
+    (Action::Trails, KeyCode::KeyT),
+    (Action::Bloom, KeyCode::KeyB),
+    (Action::Collisions, KeyCode::KeyC),
+    (Action::Sph, KeyCode::KeyL),
+    (Action::LeftEdge, KeyCode::F1),
+    (Action::RightEdge, KeyCode::F2),
+    (Action::TopEdge, KeyCode::F3),
+    (Action::BottomEdge, KeyCode::F4),
+];
+
+/// Which key does what: the defaults, with the actions from the config file moved to the keys it
+/// gives. Keys are named as in winit's `KeyCode`, such as `KeyQ`, `Digit1`, `Space` or `F5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    actions: HashMap<KeyCode, Action>,
+}
+
+impl KeyBindings {
+    pub fn new(overrides: &HashMap<Action, KeyCode>) -> Self {
+        let mut actions: HashMap<_, _> = DEFAULT_BINDINGS
+            .into_iter()
+            .filter(|(action, _)| !overrides.contains_key(action))
+            .map(|(action, key)| (key, action))
+            .collect();
+        // An action moved onto the default key of another replaces it.
+        actions.extend(overrides.iter().map(|(&action, &key)| (key, action)));
+        Self { actions }
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.actions.get(&key).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_move_actions() {
+        let overrides = HashMap::from([(Action::Pause, KeyCode::KeyP)]);
+        let bindings = KeyBindings::new(&overrides);
+
+        // P no longer changes the pixel scale, and space is free.
+        assert_eq!(bindings.action(KeyCode::KeyP), Some(Action::Pause));
+        assert_eq!(bindings.action(KeyCode::Space), None);
+        assert_eq!(bindings.action(KeyCode::KeyH), Some(Action::Hud));
+    }
+}
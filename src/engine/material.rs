@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+// Material ids as understood by the shaders. Keep in sync with the constants in `cs`.
+const SAND: u32 = 0;
+const EXPLOSIVE: u32 = 1;
+const FIRE: u32 = 2;
+const SMOKE: u32 = 3;
+
+/// What a particle is made of.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Material {
+    Sand,
+    /// Detonates when caught in an explosion, setting off one of its own.
+    Explosive,
+    /// Rises and cools down, turning into smoke.
+    Fire,
+    /// Rises and fades away.
+    Smoke,
+}
+
+/// The id of a particle's material is stored in the low bits of its `material` field, the bits
+/// above are a random number giving each grain its own shade.
+pub(crate) const MATERIAL_ID_BITS: u32 = 8;
+
+impl Material {
+    /// Every material, ordered by id.
+    pub const ALL: [Material; 4] = [
+        Material::Sand,
+        Material::Explosive,
+        Material::Fire,
+        Material::Smoke,
+    ];
+
+    pub(crate) fn id(self) -> u32 {
+        match self {
+            Material::Sand => SAND,
+            Material::Explosive => EXPLOSIVE,
+            Material::Fire => FIRE,
+            Material::Smoke => SMOKE,
+        }
+    }
+
+    /// The material with the given id, if there is one.
+    pub(crate) fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    /// Returns the next material the spawn tool cycles to.
+    pub fn cycle(self) -> Self {
+        match self {
+            Material::Sand => Material::Explosive,
+            Material::Explosive => Material::Fire,
+            Material::Fire => Material::Smoke,
+            Material::Smoke => Material::Sand,
+        }
+    }
+
+    /// Heat a new particle starts with. Fire and smoke burn out as it drops.
+    pub fn initial_heat(self) -> f32 {
+        match self {
+            Material::Fire => 1.0,
+            // Keep in sync with `SMOKE_HEAT` in `cs`.
+            Material::Smoke => 0.4,
+            Material::Sand | Material::Explosive => 0.0,
+        }
+    }
+
+    pub fn default_style(self) -> MaterialStyle {
+        let style = MaterialStyle {
+            color: [0.0; 3],
+            variation: 0.0,
+            emissive: 0.0,
+            velocity_tint: 0.0,
+            heat_tint: 0.0,
+        };
+        match self {
+            Material::Sand => MaterialStyle {
+                color: [0.76, 0.62, 0.36],
+                variation: 0.15,
+                velocity_tint: 0.4,
+                ..style
+            },
+            Material::Explosive => MaterialStyle {
+                color: [0.8, 0.1, 0.1],
+                variation: 0.1,
+                ..style
+            },
+            Material::Fire => MaterialStyle {
+                color: [1.0, 0.45, 0.1],
+                variation: 0.2,
+                emissive: 1.5,
+                heat_tint: 0.6,
+                ..style
+            },
+            Material::Smoke => MaterialStyle {
+                color: [0.35, 0.35, 0.38],
+                variation: 0.1,
+                heat_tint: 0.7,
+                ..style
+            },
+        }
+    }
+}
+
+/// How particles of one material are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialStyle {
+    pub color: [f32; 3],
+    /// How much the brightness of single grains varies around `color`, from 0.0 to 1.0.
+    pub variation: f32,
+    /// Light given off on top of `color`, making the material glow.
+    pub emissive: f32,
+    /// How much moving grains brighten, reaching this at `max_speed`.
+    pub velocity_tint: f32,
+    /// How much grains darken as they cool down, from 0.0 to 1.0.
+    pub heat_tint: f32,
+}
+
+/// Changes to the default style of one material. Every field is optional.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleOverride {
+    pub color: Option<[f32; 3]>,
+    pub variation: Option<f32>,
+    pub emissive: Option<f32>,
+    pub velocity_tint: Option<f32>,
+    pub heat_tint: Option<f32>,
+}
+
+/// The style of every material, as uploaded to `vs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    styles: [MaterialStyle; Material::ALL.len()],
+}
+
+impl Palette {
+    /// The default styles, changed by the `[materials]` section of the config file.
+    pub fn new(overrides: &HashMap<Material, StyleOverride>) -> Self {
+        let styles = Material::ALL.map(|material| {
+            let default = material.default_style();
+            let Some(o) = overrides.get(&material) else {
+                return default;
+            };
+            MaterialStyle {
+                color: o.color.unwrap_or(default.color),
+                variation: o.variation.unwrap_or(default.variation),
+                emissive: o.emissive.unwrap_or(default.emissive),
+                velocity_tint: o.velocity_tint.unwrap_or(default.velocity_tint),
+                heat_tint: o.heat_tint.unwrap_or(default.heat_tint),
+            }
+        });
+        Self { styles }
+    }
+
+    /// Styles ordered by material id.
+    pub(crate) fn styles(&self) -> &[MaterialStyle] {
+        &self.styles
+    }
+}
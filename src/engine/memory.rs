@@ -1,65 +1,87 @@
 use std::sync::Arc;
 
+use tracing::{debug, warn};
+
 use vulkano::{
-    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        AllocateBufferError, Buffer, BufferCreateInfo, BufferUsage, Subbuffer,
+    },
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        CopyBufferInfo,
+        CopyBufferInfo, PrimaryAutoCommandBuffer,
     },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
     },
     device::{DeviceOwned, Queue},
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
-    pipeline::{ComputePipeline, Pipeline},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
     sync::GpuFuture,
-    DeviceSize,
+    DeviceSize, Validated,
 };
-use winit::dpi::PhysicalPosition;
 
-use super::MyVertex;
+use super::{error::Error, shaders::compact_cs, spatial_hash::ParticleBuffers, MyVertex};
 
 const START_CAPACITY: u32 = 1024;
 
 pub struct DynMemoryManager {
     pub(crate) device_local_buffer: Subbuffer<[MyVertex]>,
     pub(crate) descriptor_set: Arc<DescriptorSet>,
+    // Used by `SpatialHash`, sized like the vertex buffer.
+    pub(crate) hash_buffers: ParticleBuffers,
     size: u32,
     capacity: u32,
+    // The capacity is never grown past this. Lowered when the device runs out of memory.
+    max_particles: u32,
+    // Whether refusing particles over the budget was reported, so it is only reported once.
+    reported_full: bool,
+    // Compaction writes the surviving particles here before they are copied back.
+    scratch_buffer: Subbuffer<[MyVertex]>,
+    alive_counter: Subbuffer<[u32]>,
+    compact_descriptor_set: Arc<DescriptorSet>,
+    // Particles waiting for `record_pending`, and the staging memory they are uploaded from.
+    pending: Vec<MyVertex>,
+    upload_allocator: SubbufferAllocator,
     memory_allocator: Arc<StandardMemoryAllocator>,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     queue: Arc<Queue>,
     compute_pipeline: Arc<ComputePipeline>,
+    compact_pipeline: Arc<ComputePipeline>,
 }
 
 impl DynMemoryManager {
-    // starts with 0 pixels, and a capacity of 1024.
+    // starts with 0 pixels, and a capacity of 1024, or `max_particles` if that is less.
     pub fn new(
         memory_allocator: Arc<StandardMemoryAllocator>,
         descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
         command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
         queue: Arc<Queue>,
         compute_pipeline: Arc<ComputePipeline>,
-    ) -> Self {
-        let device_local_buffer = Buffer::new_slice::<MyVertex>(
+        compact_pipeline: Arc<ComputePipeline>,
+        max_particles: u32,
+    ) -> Result<Self, Error> {
+        let max_particles = max_particles.max(1);
+        let capacity = START_CAPACITY.min(max_particles);
+        let device_local_buffer = new_vertex_buffer(&memory_allocator, capacity)?;
+        let scratch_buffer = new_vertex_buffer(&memory_allocator, capacity)?;
+        let hash_buffers = ParticleBuffers::new(&memory_allocator, capacity)?;
+
+        let alive_counter = Buffer::from_iter(
             memory_allocator.clone(),
             BufferCreateInfo {
-                // Specify use as a storage buffer, vertex buffer, and transfer destination.
-                usage: BufferUsage::STORAGE_BUFFER
-                    | BufferUsage::TRANSFER_DST
-                    | BufferUsage::VERTEX_BUFFER
-                    | BufferUsage::TRANSFER_SRC,
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
                 ..Default::default()
             },
             AllocationCreateInfo {
-                // Specify this buffer will only be used by the device.
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                // Read back by the host after every compaction.
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
                 ..Default::default()
             },
-            START_CAPACITY as DeviceSize,
-        )
-        .unwrap();
+            [0u32],
+        )?;
 
         // initialize descriptor_set
         let descriptor_set = DescriptorSet::new(
@@ -71,29 +93,112 @@ impl DynMemoryManager {
                 WriteDescriptorSet::buffer(0, device_local_buffer.clone()),
             ],
             [],
-        )
-        .unwrap();
+        )?;
+
+        let compact_descriptor_set = new_compact_descriptor_set(
+            &descriptor_set_allocator,
+            &compact_pipeline,
+            &device_local_buffer,
+            &scratch_buffer,
+            &alive_counter,
+        )?;
+
+        let upload_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::TRANSFER_SRC,
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        );
 
-        Self {
+        Ok(Self {
             device_local_buffer,
             descriptor_set,
+            hash_buffers,
             size: 0,
-            capacity: START_CAPACITY,
+            capacity,
+            max_particles,
+            reported_full: false,
+            scratch_buffer,
+            alive_counter,
+            compact_descriptor_set,
+            pending: Vec::new(),
+            upload_allocator,
             memory_allocator: memory_allocator.clone(),
             descriptor_set_allocator: descriptor_set_allocator.clone(),
             command_buffer_allocator: command_buffer_allocator.clone(),
             queue: queue.clone(),
             compute_pipeline: compute_pipeline.clone(),
-        }
+            compact_pipeline: compact_pipeline.clone(),
+        })
     }
 
     pub fn size(&self) -> u32 {
         self.size
     }
 
-    pub fn add_pixels(&mut self, num_pixels: u32, pos: PhysicalPosition<f64>) {
-        if self.size + num_pixels > self.capacity {
-            self.recreate_buffer(std::cmp::max(self.capacity * 2, self.size + num_pixels));
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub fn max_particles(&self) -> u32 {
+        self.max_particles
+    }
+
+    /// Whether the particle budget is used up, so new particles are refused.
+    pub fn is_full(&self) -> bool {
+        self.size >= self.max_particles
+    }
+
+    /// Number of particles waiting for the next `record_pending`.
+    pub fn queued(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queues particles to be appended by the next `record_pending`. Unlike `add_particles`, this
+    /// doesn't wait for the GPU, so it is the way to spawn particles every frame.
+    pub fn queue_particles(&mut self, vertices: &[MyVertex]) {
+        self.pending.extend_from_slice(vertices);
+    }
+
+    /// Records the upload of every queued particle into the frame's command buffer. They count
+    /// towards `size` right away, so this must be recorded before anything using the particles.
+    pub fn record_pending(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), Error> {
+        let num_pixels = self.make_room(self.pending.len())?;
+        self.pending.truncate(num_pixels as usize);
+        if num_pixels == 0 {
+            return Ok(());
+        }
+
+        let staging_buffer = self
+            .upload_allocator
+            .allocate_slice(num_pixels as DeviceSize)?;
+        staging_buffer.write()?.copy_from_slice(&self.pending);
+        self.pending.clear();
+
+        builder.copy_buffer(CopyBufferInfo::buffers(
+            staging_buffer,
+            self.device_local_buffer
+                .clone()
+                .slice(self.size as DeviceSize..(self.size + num_pixels) as DeviceSize),
+        ))?;
+
+        self.size += num_pixels;
+        Ok(())
+    }
+
+    /// Appends the given particles to the end of the buffer, growing it if needed. Blocks until
+    /// the copy is done. Particles over the budget are dropped.
+    pub fn add_particles(&mut self, vertices: &[MyVertex]) -> Result<(), Error> {
+        let num_pixels = self.make_room(vertices.len())?;
+        let vertices = &vertices[..num_pixels as usize];
+        if num_pixels == 0 {
+            return Ok(());
         }
 
         let staging_buffer = Buffer::new_slice::<MyVertex>(
@@ -107,94 +212,106 @@ impl DynMemoryManager {
                 ..Default::default()
             },
             num_pixels as DeviceSize,
-        )
-        .unwrap();
-
-        {
-            let mut mapping = staging_buffer.write().unwrap();
-            for vertex in mapping.iter_mut() {
-                *vertex = MyVertex {
-                    pos: pos.into(),
-                    vel: [0.0, 0.0],
-                };
-            }
-        }
+        )?;
+
+        staging_buffer.write()?.copy_from_slice(vertices);
 
         let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
             self.command_buffer_allocator.clone(),
             self.queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        )?;
 
-        command_buffer_builder
-            .copy_buffer(CopyBufferInfo::buffers(
-                staging_buffer.clone(),
-                self.device_local_buffer
-                    .clone()
-                    .slice(self.size as DeviceSize..(self.size + num_pixels) as DeviceSize),
-            ))
-            .unwrap();
+        command_buffer_builder.copy_buffer(CopyBufferInfo::buffers(
+            staging_buffer.clone(),
+            self.device_local_buffer
+                .clone()
+                .slice(self.size as DeviceSize..(self.size + num_pixels) as DeviceSize),
+        ))?;
 
-        let command_buffer = command_buffer_builder.build().unwrap();
+        let command_buffer = command_buffer_builder.build()?;
 
         let device = self.memory_allocator.device();
 
         let future = vulkano::sync::now(device.clone())
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?;
 
-        future.wait(None).unwrap();
+        future.wait(None)?;
 
         self.size += num_pixels;
+        Ok(())
     }
 
-    fn recreate_buffer(&mut self, new_capacity: u32) {
-        println!("changing??? new capacity is: {}", new_capacity);
-        let new_buffer = Buffer::new_slice::<MyVertex>(
-            self.memory_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::STORAGE_BUFFER
-                    | BufferUsage::TRANSFER_DST
-                    | BufferUsage::VERTEX_BUFFER
-                    | BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
-                ..Default::default()
-            },
-            new_capacity as DeviceSize,
-        )
-        .unwrap();
+    /// Grows the buffer for `count` more particles if needed, returning how many of them fit. The
+    /// rest are refused, being over the budget.
+    fn make_room(&mut self, count: usize) -> Result<u32, Error> {
+        // Growing still blocks, but the capacity doubles so it rarely happens.
+        let wanted = (self.size as usize + count).min(self.max_particles as usize) as u32;
+        if wanted > self.capacity {
+            self.grow(wanted)?;
+        }
+
+        let room = (self.capacity - self.size) as usize;
+        if count > room && !self.reported_full {
+            warn!(
+                max_particles = self.max_particles,
+                "particle budget reached, new particles are refused"
+            );
+            self.reported_full = true;
+        }
+        Ok(count.min(room) as u32)
+    }
+
+    /// Grows the buffer to hold at least `needed` particles, doubling the capacity up to the
+    /// budget. When the device can't allocate that much, smaller steps are tried down to `needed`,
+    /// and if even that fails, the budget is lowered to the current capacity.
+    fn grow(&mut self, needed: u32) -> Result<(), Error> {
+        let (capacity, max_particles) = (self.capacity, self.max_particles);
+        match try_capacities(capacity, needed, max_particles, |new_capacity| {
+            self.recreate_buffer(new_capacity)
+        }) {
+            Err(e @ Error::OutOfMemory { .. }) => {
+                warn!("{e}, lowering the particle budget to {}", self.capacity);
+                self.max_particles = self.capacity;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn recreate_buffer(&mut self, new_capacity: u32) -> Result<(), Error> {
+        debug!(capacity = new_capacity, "growing the vertex buffer");
+        let out_of_memory = |e| Error::allocation(new_capacity, e);
+        // Everything sized to the capacity is allocated before anything changes, so a failure
+        // leaves the manager as it was.
+        let new_buffer =
+            new_vertex_buffer(&self.memory_allocator, new_capacity).map_err(out_of_memory)?;
+        let scratch_buffer =
+            new_vertex_buffer(&self.memory_allocator, new_capacity).map_err(out_of_memory)?;
+        let hash_buffers =
+            ParticleBuffers::new(&self.memory_allocator, new_capacity).map_err(out_of_memory)?;
 
         let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
             self.command_buffer_allocator.clone(),
             self.queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        )?;
 
-        command_buffer_builder
-            .copy_buffer(CopyBufferInfo::buffers(
-                self.device_local_buffer.clone(),
-                new_buffer.clone(),
-            ))
-            .unwrap();
+        command_buffer_builder.copy_buffer(CopyBufferInfo::buffers(
+            self.device_local_buffer.clone(),
+            new_buffer.clone(),
+        ))?;
 
-        let command_buffer = command_buffer_builder.build().unwrap();
+        let command_buffer = command_buffer_builder.build()?;
 
         let device = self.memory_allocator.device();
 
         let future = vulkano::sync::now(device.clone())
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?;
 
-        future.wait(None).unwrap();
+        future.wait(None)?;
 
         // now recreate descriptor_set
         let new_descriptor_set = DescriptorSet::new(
@@ -206,64 +323,277 @@ impl DynMemoryManager {
                 WriteDescriptorSet::buffer(0, new_buffer.clone()),
             ],
             [],
-        )
-        .unwrap();
-        println!(
-            "descriptor_set changed to a mindboggling size of: {}",
-            new_buffer.len()
-        );
+        )?;
 
         self.descriptor_set = new_descriptor_set;
 
+        // The scratch buffer holds nothing between compactions, so it is simply replaced.
+        self.scratch_buffer = scratch_buffer;
+        self.compact_descriptor_set = new_compact_descriptor_set(
+            &self.descriptor_set_allocator,
+            &self.compact_pipeline,
+            &new_buffer,
+            &self.scratch_buffer,
+            &self.alive_counter,
+        )?;
+
         self.device_local_buffer = new_buffer;
+        self.hash_buffers = hash_buffers;
         self.capacity = new_capacity;
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    pub fn debug_buffer(&self) {
-        let debug_buffer = Buffer::new_slice::<MyVertex>(
+    /// Drops every particle `cs` marked as removed, packing the rest to the front of the buffer.
+    /// Blocks until the GPU is done, so it is meant to be run every few frames.
+    pub fn remove_dead(&mut self) -> Result<(), Error> {
+        if self.size == 0 {
+            return Ok(());
+        }
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+
+        command_buffer_builder
+            .fill_buffer(self.alive_counter.clone(), 0)?
+            .push_constants(
+                self.compact_pipeline.layout().clone(),
+                0,
+                compact_cs::PushConstants {
+                    particle_count: self.size,
+                },
+            )?
+            .bind_pipeline_compute(self.compact_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.compact_pipeline.layout().clone(),
+                0,
+                self.compact_descriptor_set.clone(),
+            )?;
+        unsafe { command_buffer_builder.dispatch([self.size.div_ceil(1024), 1, 1]) }?;
+
+        let range = 0..self.size as DeviceSize;
+        command_buffer_builder.copy_buffer(CopyBufferInfo::buffers(
+            self.scratch_buffer.clone().slice(range.clone()),
+            self.device_local_buffer.clone().slice(range),
+        ))?;
+
+        let command_buffer = command_buffer_builder.build()?;
+
+        let device = self.memory_allocator.device();
+
+        let future = vulkano::sync::now(device.clone())
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?;
+
+        future.wait(None)?;
+
+        self.size = self.alive_counter.read()?[0];
+        // Refusing particles is reported again the next time the budget runs out.
+        if self.size < self.max_particles {
+            self.reported_full = false;
+        }
+        Ok(())
+    }
+
+    /// Removes every particle, keeping the buffer.
+    pub fn clear(&mut self) {
+        self.size = 0;
+        self.pending.clear();
+        self.reported_full = false;
+    }
+
+    /// Copies every particle back from the GPU. Blocks until the copy is done.
+    pub fn read_particles(&self) -> Result<Vec<MyVertex>, Error> {
+        if self.size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let readback_buffer = Buffer::new_slice::<MyVertex>(
             self.memory_allocator.clone(),
             BufferCreateInfo {
                 usage: BufferUsage::TRANSFER_DST,
                 ..Default::default()
             },
             AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_HOST,
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
                 ..Default::default()
             },
             self.size as DeviceSize,
-        )
-        .unwrap();
+        )?;
 
         let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
             self.command_buffer_allocator.clone(),
             self.queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        )?;
 
-        command_buffer_builder
-            .copy_buffer(CopyBufferInfo::buffers(
-                self.device_local_buffer.clone(),
-                debug_buffer.clone(),
-            ))
-            .unwrap();
+        command_buffer_builder.copy_buffer(CopyBufferInfo::buffers(
+            self.device_local_buffer
+                .clone()
+                .slice(0..self.size as DeviceSize),
+            readback_buffer.clone(),
+        ))?;
 
-        let command_buffer = command_buffer_builder.build().unwrap();
+        let command_buffer = command_buffer_builder.build()?;
 
         let device = self.memory_allocator.device();
 
         let future = vulkano::sync::now(device.clone())
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?;
 
-        future.wait(None).unwrap();
+        future.wait(None)?;
 
-        let data = debug_buffer.read().unwrap();
-        for (i, vertex) in data.iter().enumerate() {
-            println!("Vertex {}: {:?}", i, vertex);
+        // Bound to a local so the read guard is dropped before the buffer.
+        let particles = readback_buffer.read()?.to_vec();
+        Ok(particles)
+    }
+
+    #[allow(dead_code)]
+    pub fn debug_buffer(&self) {
+        for (i, vertex) in self.read_particles().unwrap().iter().enumerate() {
+            debug!("vertex {i}: {vertex:?}");
         }
     }
 }
+
+// Calls `allocate` with the capacity to grow to from `capacity` to hold `needed` particles, which
+// is double the capacity up to `max_particles`. While that runs out of memory, smaller steps are
+// tried down to `needed`, and the error of that last try is returned.
+fn try_capacities(
+    capacity: u32,
+    needed: u32,
+    max_particles: u32,
+    mut allocate: impl FnMut(u32) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut new_capacity = capacity.saturating_mul(2).max(needed).min(max_particles);
+    loop {
+        match allocate(new_capacity) {
+            Err(Error::OutOfMemory { .. }) if new_capacity > needed => {
+                debug!(
+                    capacity = new_capacity,
+                    "out of GPU memory, trying a smaller step"
+                );
+                new_capacity = (capacity + (new_capacity - capacity) / 2).max(needed);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn new_vertex_buffer(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    capacity: u32,
+) -> Result<Subbuffer<[MyVertex]>, Validated<AllocateBufferError>> {
+    Buffer::new_slice::<MyVertex>(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            // Specify use as a storage buffer, vertex buffer, and transfer destination.
+            usage: BufferUsage::STORAGE_BUFFER
+                | BufferUsage::TRANSFER_DST
+                | BufferUsage::VERTEX_BUFFER
+                | BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            // Specify this buffer will only be used by the device.
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        capacity as DeviceSize,
+    )
+}
+
+fn new_compact_descriptor_set(
+    descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    compact_pipeline: &Arc<ComputePipeline>,
+    source: &Subbuffer<[MyVertex]>,
+    target: &Subbuffer<[MyVertex]>,
+    alive_counter: &Subbuffer<[u32]>,
+) -> Result<Arc<DescriptorSet>, Error> {
+    let descriptor_set = DescriptorSet::new(
+        descriptor_set_allocator.clone(),
+        compact_pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::buffer(0, source.clone()),
+            WriteDescriptorSet::buffer(1, target.clone()),
+            WriteDescriptorSet::buffer(2, alive_counter.clone()),
+        ],
+        [],
+    )?;
+    Ok(descriptor_set)
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{memory::allocator::MemoryAllocatorError, VulkanError};
+
+    use super::*;
+
+    fn out_of_memory(capacity: u32) -> Error {
+        let source =
+            AllocateBufferError::AllocateMemory(MemoryAllocatorError::AllocateDeviceMemory(
+                Validated::Error(VulkanError::OutOfDeviceMemory),
+            ));
+        Error::allocation(capacity, Validated::Error(source))
+    }
+
+    // The capacities `try_capacities` tries when the device can hold up to `available`.
+    fn tries(capacity: u32, needed: u32, max_particles: u32, available: u32) -> (Vec<u32>, bool) {
+        let mut tried = Vec::new();
+        let result = try_capacities(capacity, needed, max_particles, |new_capacity| {
+            tried.push(new_capacity);
+            if new_capacity <= available {
+                Ok(())
+            } else {
+                Err(out_of_memory(new_capacity))
+            }
+        });
+        (tried, result.is_ok())
+    }
+
+    #[test]
+    fn doubles_up_to_the_budget() {
+        assert_eq!(tries(1024, 1100, 1 << 20, u32::MAX), (vec![2048], true));
+        assert_eq!(tries(1024, 5000, 1 << 20, u32::MAX), (vec![5000], true));
+        assert_eq!(tries(2048, 2100, 3000, u32::MAX), (vec![3000], true));
+    }
+
+    #[test]
+    fn takes_smaller_steps_when_out_of_memory() {
+        assert_eq!(
+            tries(1024, 1100, 1 << 20, 1300),
+            (vec![2048, 1536, 1280], true)
+        );
+    }
+
+    #[test]
+    fn fails_when_even_the_needed_capacity_is_out_of_memory() {
+        assert_eq!(
+            tries(1024, 1100, 1 << 20, 1000),
+            (vec![2048, 1536, 1280, 1152, 1100], false)
+        );
+    }
+
+    #[test]
+    fn only_running_out_of_memory_takes_smaller_steps() {
+        let mut tried = 0;
+        let result = try_capacities(1024, 1100, 1 << 20, |_| {
+            tried += 1;
+            Err(Error::DeviceLost)
+        });
+        assert!(matches!(result, Err(Error::DeviceLost)));
+        assert_eq!(tried, 1);
+
+        let source = AllocateBufferError::AllocateMemory(MemoryAllocatorError::FindMemoryType);
+        assert!(matches!(
+            Error::allocation(1024, Validated::Error(source)),
+            Error::Vulkan(_)
+        ));
+        assert!(matches!(out_of_memory(1024), Error::OutOfMemory { .. }));
+    }
+}
@@ -0,0 +1,170 @@
+use serde::Deserialize;
+
+/// Number of cells along each axis of the obstacle grid. Keep in sync with
+/// `OBSTACLE_GRID_SIZE` in `cs`.
+pub const OBSTACLE_GRID_SIZE: usize = 512;
+
+/// Width of an obstacle cell in normalized device coordinates.
+pub const CELL_SIZE: f32 = 2.0 / OBSTACLE_GRID_SIZE as f32;
+
+/// Color walls are drawn with.
+pub const WALL_COLOR: [f32; 4] = [0.5, 0.5, 0.55, 1.0];
+
+/// Settings of the wall tool.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WallBrush {
+    /// Brush radius, and half the thickness of lines, in normalized device coordinates.
+    pub radius: f32,
+}
+
+impl Default for WallBrush {
+    fn default() -> Self {
+        Self { radius: 0.01 }
+    }
+}
+
+/// A bit per cell of a grid covering the world, set where particles can't go. Particles check it
+/// in `cs` before every move.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ObstacleMask {
+    bits: Vec<u32>,
+}
+
+impl ObstacleMask {
+    pub fn new() -> Self {
+        Self {
+            bits: vec![0; OBSTACLE_GRID_SIZE * OBSTACLE_GRID_SIZE / 32],
+        }
+    }
+
+    /// Rebuilds a mask from the output of `words`. Returns `None` if the length doesn't match.
+    pub fn from_words(bits: Vec<u32>) -> Option<Self> {
+        (bits.len() == OBSTACLE_GRID_SIZE * OBSTACLE_GRID_SIZE / 32).then_some(Self { bits })
+    }
+
+    /// Row-major cells packed 32 to a word, as uploaded to `cs`.
+    pub fn words(&self) -> &[u32] {
+        &self.bits
+    }
+
+    pub fn set(&mut self, x: usize, y: usize) {
+        let bit = y * OBSTACLE_GRID_SIZE + x;
+        self.bits[bit / 32] |= 1 << (bit % 32);
+    }
+
+    pub fn unset(&mut self, x: usize, y: usize) {
+        let bit = y * OBSTACLE_GRID_SIZE + x;
+        self.bits[bit / 32] &= !(1 << (bit % 32));
+    }
+
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        let bit = y * OBSTACLE_GRID_SIZE + x;
+        self.bits[bit / 32] & (1 << (bit % 32)) != 0
+    }
+
+    /// Every set cell, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..OBSTACLE_GRID_SIZE)
+            .flat_map(|y| (0..OBSTACLE_GRID_SIZE).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.is_set(x, y))
+    }
+
+    /// Sets, or clears if `solid` is false, every cell whose center lies within `radius` of
+    /// `pos`.
+    pub fn paint(&mut self, pos: [f32; 2], radius: f32, solid: bool) {
+        self.fill_where(pos, [radius, radius], solid, |d| d[0].hypot(d[1]) <= radius);
+    }
+
+    /// Paints a line of the given half thickness from `from` to `to`.
+    pub fn line(&mut self, from: [f32; 2], to: [f32; 2], radius: f32) {
+        let length = (to[0] - from[0]).hypot(to[1] - from[1]);
+        let steps = (length / (0.5 * CELL_SIZE)).ceil().max(1.0) as usize;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let pos = [
+                from[0] + (to[0] - from[0]) * t,
+                from[1] + (to[1] - from[1]) * t,
+            ];
+            self.paint(pos, radius, true);
+        }
+    }
+
+    /// Fills the rectangle with opposite corners `a` and `b`.
+    pub fn rect(&mut self, a: [f32; 2], b: [f32; 2]) {
+        let center = [0.5 * (a[0] + b[0]), 0.5 * (a[1] + b[1])];
+        let half = [0.5 * (a[0] - b[0]).abs(), 0.5 * (a[1] - b[1]).abs()];
+        self.fill_where(center, half, true, |_| true);
+    }
+
+    // Sets or clears the cells within `half` of `center` on both axes for which `inside` holds,
+    // given their offset from `center`.
+    fn fill_where(
+        &mut self,
+        center: [f32; 2],
+        half: [f32; 2],
+        solid: bool,
+        inside: impl Fn([f32; 2]) -> bool,
+    ) {
+        let first = |c: f32| ((c + 1.0) / CELL_SIZE).floor().max(0.0) as usize;
+        let last = |c: f32| (((c + 1.0) / CELL_SIZE) as usize).min(OBSTACLE_GRID_SIZE - 1);
+
+        for y in first(center[1] - half[1])..=last(center[1] + half[1]) {
+            for x in first(center[0] - half[0])..=last(center[0] + half[0]) {
+                let [cx, cy] = cell_center(x, y);
+                let d = [cx - center[0], cy - center[1]];
+                if d[0].abs() <= half[0] && d[1].abs() <= half[1] && inside(d) {
+                    if solid {
+                        self.set(x, y);
+                    } else {
+                        self.unset(x, y);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The cell containing `pos`, if it is inside the world.
+pub fn cell_of(pos: [f32; 2]) -> Option<(usize, usize)> {
+    let x = ((pos[0] + 1.0) / CELL_SIZE).floor();
+    let y = ((pos[1] + 1.0) / CELL_SIZE).floor();
+    let range = 0.0..OBSTACLE_GRID_SIZE as f32;
+    (range.contains(&x) && range.contains(&y)).then_some((x as usize, y as usize))
+}
+
+pub fn cell_center(x: usize, y: usize) -> [f32; 2] {
+    [
+        -1.0 + (x as f32 + 0.5) * CELL_SIZE,
+        -1.0 + (y as f32 + 0.5) * CELL_SIZE,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_connects_its_end_points() {
+        let mut mask = ObstacleMask::new();
+        mask.line([-0.5, 0.0], [0.5, 0.0], CELL_SIZE);
+
+        for pos in [[-0.5, 0.0], [0.0, 0.0], [0.5, 0.0]] {
+            let (x, y) = cell_of(pos).unwrap();
+            assert!(mask.is_set(x, y), "{pos:?}");
+        }
+        let (x, y) = cell_of([0.0, 0.1]).unwrap();
+        assert!(!mask.is_set(x, y));
+    }
+
+    #[test]
+    fn rect_fills_between_corners() {
+        let mut mask = ObstacleMask::new();
+        // Exactly 4 by 2 cells, with corners on cell edges.
+        mask.rect([0.0, 0.0], [4.0 * CELL_SIZE, -2.0 * CELL_SIZE]);
+        assert_eq!(mask.cells().count(), 8);
+
+        mask.paint([2.0 * CELL_SIZE, -CELL_SIZE], 0.9 * CELL_SIZE, false);
+        assert_eq!(mask.cells().count(), 4);
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Deserialize;
+
+/// Global simulation constants, uploaded to the shaders every frame so they can be tuned while
+/// the simulation runs.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsParams {
+    /// Speed particles are clamped to, also used by `vs` to scale particle colors.
+    pub max_speed: f32,
+    /// Exponential decay rate of particle velocity, per second.
+    pub friction: f32,
+    /// Acceleration applied to every particle. Positive y points down the screen.
+    pub gravity: [f32; 2],
+    /// Rate at which particles inside a wind zone take on the velocity of the wind, per second.
+    pub wind_drag: f32,
+    /// Whether particles collide with each other, rather than passing through.
+    pub collisions: bool,
+    /// Collision radius of a particle, in normalized device coordinates.
+    pub particle_radius: f32,
+    pub sph: SphParams,
+}
+
+/// Smoothed-particle hydrodynamics, which makes particles behave as a liquid by pushing them
+/// apart where they are denser than `rest_density`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SphParams {
+    pub enabled: bool,
+    /// Distance over which neighbours are taken into account, in normalized device coordinates.
+    pub smoothing_radius: f32,
+    /// Density the fluid settles at, 1.0 being particles `2 * particle_radius` apart.
+    pub rest_density: f32,
+    /// How strongly density above `rest_density` pushes particles apart.
+    pub stiffness: f32,
+    /// How strongly neighbours pull each other towards their average velocity.
+    pub viscosity: f32,
+}
+
+impl Default for SphParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            smoothing_radius: 0.012,
+            rest_density: 1.0,
+            stiffness: 1.0,
+            viscosity: 0.001,
+        }
+    }
+}
+
+impl Default for PhysicsParams {
+    fn default() -> Self {
+        Self {
+            max_speed: 10.0,
+            friction: 2.0,
+            gravity: [0.0, 9.8],
+            wind_drag: 4.0,
+            collisions: true,
+            particle_radius: 0.0025,
+            sph: SphParams::default(),
+        }
+    }
+}
+
+/// A runtime adjustment of one of the parameters, bound to a key.
+#[derive(Debug, Clone, Copy)]
+pub enum Tweak {
+    MaxSpeed,
+    Friction,
+    Gravity,
+}
+
+impl PhysicsParams {
+    /// Nudges a parameter up or down by one step, keeping it in a sensible range.
+    pub fn tweak(&mut self, tweak: Tweak, increase: bool) {
+        let sign = if increase { 1.0 } else { -1.0 };
+        match tweak {
+            Tweak::MaxSpeed => self.max_speed = (self.max_speed + sign).max(0.5),
+            Tweak::Friction => self.friction = (self.friction + sign * 0.25).max(0.0),
+            Tweak::Gravity => {
+                let [x, y] = self.gravity;
+                let strength = x.hypot(y);
+                let new_strength = (strength + sign).max(0.0);
+                // Without a direction to keep, gravity starts pointing down again.
+                let dir = if strength > 0.0 {
+                    [x / strength, y / strength]
+                } else {
+                    [0.0, 1.0]
+                };
+                self.gravity = dir.map(|c| c * new_strength);
+            }
+        }
+    }
+
+    /// Rotates the gravity vector, which has the same effect as rotating the world.
+    pub fn rotate_gravity(&mut self, degrees: f32) {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let [x, y] = self.gravity;
+        self.gravity = [x * cos - y * sin, x * sin + y * cos];
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::Device,
+    pipeline::{
+        compute::ComputePipelineCreateInfo,
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        ComputePipeline, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+    shader::EntryPoint,
+};
+use winit::dpi::PhysicalSize;
+
+use super::error::Error;
+
+/// A pipeline drawing to the whole window with the first subpass of `render_pass`. Without
+/// `blend`, fragments replace what is already drawn.
+pub fn new_graphics_pipeline(
+    window_size: PhysicalSize<u32>,
+    render_pass: &Arc<RenderPass>,
+    device: &Arc<Device>,
+    shaders: [&EntryPoint; 2],
+    vertex_input_state: VertexInputState,
+    topology: PrimitiveTopology,
+    blend: Option<AttachmentBlend>,
+) -> Result<Arc<GraphicsPipeline>, Error> {
+    let stages = shaders.map(|shader| PipelineShaderStageCreateInfo::new(shader.clone()));
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+    let pipeline = GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState {
+                viewports: [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: window_size.into(),
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            }),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend,
+                    ..Default::default()
+                },
+            )),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?;
+    Ok(pipeline)
+}
+
+pub fn new_compute_pipeline(
+    device: &Arc<Device>,
+    cs: EntryPoint,
+) -> Result<Arc<ComputePipeline>, Error> {
+    let stage = PipelineShaderStageCreateInfo::new(cs);
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())?,
+    )?;
+
+    let pipeline = ComputePipeline::new(
+        device.clone(),
+        None,
+        ComputePipelineCreateInfo::stage_layout(stage, layout),
+    )?;
+    Ok(pipeline)
+}
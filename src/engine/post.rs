@@ -0,0 +1,363 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::DeviceOwned,
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{input_assembly::PrimitiveTopology, vertex_input::VertexInputState},
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
+    shader::EntryPoint,
+};
+use winit::dpi::PhysicalSize;
+
+use super::{
+    error::Error,
+    pipeline::{new_compute_pipeline, new_graphics_pipeline},
+    render::BloomSettings,
+    shaders::{bloom_blur_cs, bloom_bright_cs, composite_fs, quad_vs},
+};
+
+/// Format of the offscreen image the scene is drawn to, which keeps colors brighter than 1.0 for
+/// the bloom.
+const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+// Keep in sync with `local_size_x` and `local_size_y` of the bloom shaders.
+const BLOOM_GROUP_SIZE: u32 = 8;
+
+/// The passes drawing a frame. Everything is drawn to an HDR image with the scene render pass,
+/// then the bloom is computed from it at half resolution and both are composited onto the
+/// swapchain image.
+pub struct PostProcess {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    scene_pass: Arc<RenderPass>,
+    pipelines: BloomPipelines,
+    targets: Targets,
+}
+
+struct BloomPipelines {
+    bright: Arc<ComputePipeline>,
+    blur: Arc<ComputePipeline>,
+    quad_vs: EntryPoint,
+    composite_fs: EntryPoint,
+    sampler: Arc<Sampler>,
+}
+
+// Everything depending on the window size.
+struct Targets {
+    scene_framebuffer: Arc<Framebuffer>,
+    // The bright-pass is written to the first image, and the blur goes back and forth between
+    // both, ending in the first.
+    bloom: [Arc<ImageView>; 2],
+    bright_set: Arc<DescriptorSet>,
+    // Horizontal pass from the first image to the second, and vertical pass back.
+    blur_sets: [Arc<DescriptorSet>; 2],
+    composite_pipeline: Arc<GraphicsPipeline>,
+    composite_set: Arc<DescriptorSet>,
+}
+
+impl PostProcess {
+    /// `render_pass` is the one drawing to the swapchain images.
+    pub fn new(
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        render_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<Self, Error> {
+        let device = memory_allocator.device().clone();
+        let scene_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )?;
+
+        let pipelines = BloomPipelines {
+            bright: new_compute_pipeline(
+                &device,
+                bloom_bright_cs::load(device.clone())?
+                    .entry_point("main")
+                    .unwrap(),
+            )?,
+            blur: new_compute_pipeline(
+                &device,
+                bloom_blur_cs::load(device.clone())?
+                    .entry_point("main")
+                    .unwrap(),
+            )?,
+            quad_vs: quad_vs::load(device.clone())?.entry_point("main").unwrap(),
+            composite_fs: composite_fs::load(device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            sampler: Sampler::new(
+                device.clone(),
+                SamplerCreateInfo {
+                    mag_filter: Filter::Linear,
+                    min_filter: Filter::Linear,
+                    address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                    ..Default::default()
+                },
+            )?,
+        };
+
+        let targets = Targets::new(
+            &memory_allocator,
+            &descriptor_set_allocator,
+            &scene_pass,
+            render_pass,
+            &pipelines,
+            window_size,
+        )?;
+
+        Ok(Self {
+            memory_allocator,
+            descriptor_set_allocator,
+            scene_pass,
+            pipelines,
+            targets,
+        })
+    }
+
+    /// The render pass everything in the scene is drawn with.
+    pub fn scene_pass(&self) -> &Arc<RenderPass> {
+        &self.scene_pass
+    }
+
+    pub fn scene_framebuffer(&self) -> Arc<Framebuffer> {
+        self.targets.scene_framebuffer.clone()
+    }
+
+    /// Recreates the images and the pipelines for a new window size.
+    pub fn resize(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<(), Error> {
+        self.targets = Targets::new(
+            &self.memory_allocator,
+            &self.descriptor_set_allocator,
+            &self.scene_pass,
+            render_pass,
+            &self.pipelines,
+            window_size,
+        )?;
+        Ok(())
+    }
+
+    /// Records the bloom and drawing the result to `framebuffer`, a swapchain image. Must be
+    /// recorded after the scene render pass has ended. The render pass is left open, so overlays
+    /// can be drawn on top before ending it.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        framebuffer: Arc<Framebuffer>,
+        bloom: &BloomSettings,
+    ) -> Result<(), Error> {
+        if bloom.enabled {
+            self.record_bloom(builder, bloom)?;
+        }
+
+        let pipeline = &self.targets.composite_pipeline;
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    // The composite covers the whole image.
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                Default::default(),
+            )?
+            .bind_pipeline_graphics(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                self.targets.composite_set.clone(),
+            )?
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                composite_fs::PushConstants {
+                    bloom_intensity: if bloom.enabled { bloom.intensity } else { 0.0 },
+                },
+            )?;
+        unsafe { builder.draw(4, 1, 0, 0) }?;
+        Ok(())
+    }
+
+    fn record_bloom(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        bloom: &BloomSettings,
+    ) -> Result<(), Error> {
+        let [width, height, _] = self.targets.bloom[0].image().extent();
+        let groups = [
+            width.div_ceil(BLOOM_GROUP_SIZE),
+            height.div_ceil(BLOOM_GROUP_SIZE),
+            1,
+        ];
+
+        let bright = &self.pipelines.bright;
+        builder
+            .bind_pipeline_compute(bright.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                bright.layout().clone(),
+                0,
+                self.targets.bright_set.clone(),
+            )?
+            .push_constants(
+                bright.layout().clone(),
+                0,
+                bloom_bright_cs::PushConstants {
+                    threshold: bloom.threshold,
+                },
+            )?;
+        unsafe { builder.dispatch(groups) }?;
+
+        let blur = &self.pipelines.blur;
+        builder.bind_pipeline_compute(blur.clone())?;
+        for _ in 0..bloom.passes {
+            for (set, direction) in self.targets.blur_sets.iter().zip([[1, 0], [0, 1]]) {
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Compute,
+                        blur.layout().clone(),
+                        0,
+                        set.clone(),
+                    )?
+                    .push_constants(
+                        blur.layout().clone(),
+                        0,
+                        bloom_blur_cs::PushConstants { direction },
+                    )?;
+                unsafe { builder.dispatch(groups) }?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Targets {
+    fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+        scene_pass: &Arc<RenderPass>,
+        render_pass: &Arc<RenderPass>,
+        pipelines: &BloomPipelines,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<Self, Error> {
+        let new_image = |extent: [u32; 2], usage: ImageUsage| -> Result<_, Error> {
+            let image = Image::new(
+                memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format: HDR_FORMAT,
+                    extent: [extent[0].max(1), extent[1].max(1), 1],
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )?;
+            Ok(ImageView::new_default(image)?)
+        };
+        // The bloom shaders sample `src` and store to `dst`.
+        let bloom_set =
+            |pipeline: &Arc<ComputePipeline>, src: &Arc<ImageView>, dst: Arc<ImageView>| {
+                DescriptorSet::new(
+                    descriptor_set_allocator.clone(),
+                    pipeline.layout().set_layouts()[0].clone(),
+                    [
+                        WriteDescriptorSet::image_view_sampler(
+                            0,
+                            src.clone(),
+                            pipelines.sampler.clone(),
+                        ),
+                        WriteDescriptorSet::image_view(1, dst),
+                    ],
+                    [],
+                )
+            };
+
+        let scene = new_image(
+            window_size.into(),
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+        )?;
+        let scene_framebuffer = Framebuffer::new(
+            scene_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![scene.clone()],
+                ..Default::default()
+            },
+        )?;
+
+        let bloom_extent = [
+            window_size.width.div_ceil(2),
+            window_size.height.div_ceil(2),
+        ];
+        let usage = ImageUsage::STORAGE | ImageUsage::SAMPLED;
+        let bloom = [
+            new_image(bloom_extent, usage)?,
+            new_image(bloom_extent, usage)?,
+        ];
+        let bright_set = bloom_set(&pipelines.bright, &scene, bloom[0].clone())?;
+        let blur_sets = [
+            bloom_set(&pipelines.blur, &bloom[0], bloom[1].clone())?,
+            bloom_set(&pipelines.blur, &bloom[1], bloom[0].clone())?,
+        ];
+
+        let composite_pipeline = new_graphics_pipeline(
+            window_size,
+            render_pass,
+            render_pass.device(),
+            [&pipelines.quad_vs, &pipelines.composite_fs],
+            // The quad's corners are computed from the vertex index.
+            VertexInputState::new(),
+            PrimitiveTopology::TriangleStrip,
+            None,
+        )?;
+        let composite_set = DescriptorSet::new(
+            descriptor_set_allocator.clone(),
+            composite_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, scene, pipelines.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(
+                    1,
+                    bloom[0].clone(),
+                    pipelines.sampler.clone(),
+                ),
+            ],
+            [],
+        )?;
+
+        Ok(Self {
+            scene_framebuffer,
+            bloom,
+            bright_set,
+            blur_sets,
+            composite_pipeline,
+            composite_set,
+        })
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Deserialize;
+
+/// How particles are drawn.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    /// Every particle is a single screen pixel.
+    Points,
+    /// Particles are written into a grid of square cells `pixel_scale` screen pixels wide, which
+    /// is drawn as a texture.
+    Grid,
+}
+
+/// Views coloring every particle by one simulation quantity rather than by its material, drawn
+/// as points whatever the render mode.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugView {
+    Off,
+    /// Direction as hue, speed as brightness.
+    Velocity,
+    Temperature,
+    /// SPH density relative to the rest density, which is also what drives the pressure. Only
+    /// computed while SPH is on.
+    Density,
+    /// Whether particles are resting or moving, as told by their speed.
+    Resting,
+    /// Position in the vertex buffer, which shows how compaction reorders particles.
+    Index,
+    Material,
+}
+
+impl DebugView {
+    const ALL: [DebugView; 7] = [
+        DebugView::Off,
+        DebugView::Velocity,
+        DebugView::Temperature,
+        DebugView::Density,
+        DebugView::Resting,
+        DebugView::Index,
+        DebugView::Material,
+    ];
+
+    /// Id of the view as understood by `debug_vs`.
+    pub fn id(self) -> u32 {
+        Self::ALL.iter().position(|&view| view == self).unwrap() as u32
+    }
+
+    pub fn cycle(self) -> Self {
+        Self::ALL[(self.id() as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Settings of how the world is drawn.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub mode: RenderMode,
+    /// Width of a grid cell in screen pixels.
+    pub pixel_scale: u32,
+    pub bloom: BloomSettings,
+    pub trails: TrailSettings,
+    pub debug_view: DebugView,
+}
+
+/// Glow around materials brighter than `threshold`, mostly emissive ones.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Brightness above which a color glows. Colors are clamped to 1.0 when presented, so only
+    /// colors pushed past it by `emissive` glow at the default.
+    pub threshold: f32,
+    /// Strength of the glow added back onto the scene.
+    pub intensity: f32,
+    /// Number of blur passes, each spreading the glow further.
+    pub passes: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            intensity: 0.8,
+            passes: 2,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            mode: RenderMode::Points,
+            pixel_scale: 2,
+            bloom: BloomSettings::default(),
+            trails: TrailSettings::default(),
+            debug_view: DebugView::Off,
+        }
+    }
+}
+
+/// How particles are drawn over the trails left behind.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailBlend {
+    /// Particles add their color, so overlapping trails glow brighter.
+    Additive,
+    /// Particles are drawn over the trails with the alpha of their material.
+    Alpha,
+}
+
+impl TrailBlend {
+    pub fn toggle(self) -> Self {
+        match self {
+            TrailBlend::Additive => TrailBlend::Alpha,
+            TrailBlend::Alpha => TrailBlend::Additive,
+        }
+    }
+}
+
+/// Streaks left behind by particles in `RenderMode::Points`. Rather than clearing every frame,
+/// particles are accumulated in an image which fades out over time.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrailSettings {
+    pub enabled: bool,
+    /// Rate at which the trails fade out, per second.
+    pub fade: f32,
+    pub blend: TrailBlend,
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fade: 4.0,
+            blend: TrailBlend::Additive,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            RenderMode::Points => RenderMode::Grid,
+            RenderMode::Grid => RenderMode::Points,
+        };
+    }
+
+    /// Cycles the grid cells through 1, 2 and 4 screen pixels wide.
+    pub fn cycle_pixel_scale(&mut self) {
+        self.pixel_scale = match self.pixel_scale {
+            1 => 2,
+            2 => 4,
+            _ => 1,
+        };
+    }
+}
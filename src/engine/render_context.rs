@@ -6,9 +6,12 @@ use vulkano::{
     swapchain::Swapchain,
     sync::GpuFuture,
 };
-use winit::{dpi::PhysicalPosition, window::Window};
+use winit::{dpi::PhysicalPosition, keyboard::ModifiersState, window::Window};
 
-use super::MouseState;
+use super::{
+    debug_view::DebugRenderer, grid::GridRenderer, hud::Hud, post::PostProcess, trails::Trails,
+    MouseState,
+};
 
 pub struct RenderContext {
     pub window: Arc<Window>,
@@ -18,12 +21,20 @@ pub struct RenderContext {
     pub vs: EntryPoint,
     pub fs: EntryPoint,
     pub pipeline: Arc<GraphicsPipeline>,
+    pub solid_vs: EntryPoint,
+    pub solid_pipeline: Arc<GraphicsPipeline>,
+    pub grid: GridRenderer,
+    pub trails: Trails,
+    pub debug: DebugRenderer,
+    pub hud: Hud,
+    pub post: PostProcess,
     pub recreate_swapchain: bool,
     pub previous_frame_end: Option<Box<dyn GpuFuture>>,
     pub start_time: SystemTime,
     pub last_frame_time: SystemTime,
     pub cursor_pos: PhysicalPosition<f64>,
     pub mouse_state: MouseState,
+    pub modifiers: ModifiersState,
 }
 
 // pub enum GameState {}
@@ -0,0 +1,553 @@
+use serde::Deserialize;
+
+use super::{
+    boundary::{Boundaries, Boundary},
+    hash,
+    obstacles::{cell_center, cell_of, ObstacleMask, CELL_SIZE, OBSTACLE_GRID_SIZE},
+};
+
+// Bodies are stepped several times per frame so fast ones don't sink deep into the floor, and
+// more often when they are fast enough to pass through thin walls otherwise.
+const SUBSTEPS: u32 = 4;
+const MAX_SUBSTEPS: u32 = 64;
+
+/// Settings shared by all rigid bodies.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BodySettings {
+    /// Speed of an impact, in normalized device coordinates per second, that breaks a body
+    /// apart into loose particles.
+    pub shatter_speed: f32,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl Default for BodySettings {
+    fn default() -> Self {
+        Self {
+            shatter_speed: 5.0,
+            restitution: 0.2,
+            friction: 0.5,
+        }
+    }
+}
+
+/// A solid object made of obstacle cells, which moves and rotates as one piece.
+pub struct RigidBody {
+    pos: [f32; 2],
+    angle: f32,
+    vel: [f32; 2],
+    angular_vel: f32,
+    // Shape in body space, one entry per cell, row-major.
+    width: usize,
+    height: usize,
+    solid: Vec<bool>,
+    // Center of mass in body space cells.
+    center: [f32; 2],
+    // Every cell weighs 1, and distances are in normalized device coordinates.
+    inv_mass: f32,
+    inv_inertia: f32,
+    pub color: [f32; 4],
+}
+
+struct Contact {
+    point: [f32; 2],
+    normal: [f32; 2],
+    depth: f32,
+}
+
+impl RigidBody {
+    /// Creates a body whose center of mass is at `pos`. Returns `None` for an empty shape.
+    pub fn new(
+        pos: [f32; 2],
+        width: usize,
+        height: usize,
+        solid: Vec<bool>,
+        color: [f32; 4],
+    ) -> Option<Self> {
+        let cells: Vec<[f32; 2]> = (0..width * height)
+            .filter(|&i| solid[i])
+            .map(|i| [(i % width) as f32 + 0.5, (i / width) as f32 + 0.5])
+            .collect();
+        if cells.is_empty() {
+            return None;
+        }
+
+        let mass = cells.len() as f32;
+        let center = [
+            cells.iter().map(|c| c[0]).sum::<f32>() / mass,
+            cells.iter().map(|c| c[1]).sum::<f32>() / mass,
+        ];
+        let inertia: f32 = cells
+            .iter()
+            .map(|c| {
+                ((c[0] - center[0]) * CELL_SIZE).powi(2) + ((c[1] - center[1]) * CELL_SIZE).powi(2)
+            })
+            .sum();
+
+        Some(Self {
+            pos,
+            angle: 0.0,
+            vel: [0.0, 0.0],
+            angular_vel: 0.0,
+            width,
+            height,
+            solid,
+            center,
+            inv_mass: 1.0 / mass,
+            // A single cell has no inertia, it then simply doesn't rotate.
+            inv_inertia: if inertia > 0.0 { 1.0 / inertia } else { 0.0 },
+            color,
+        })
+    }
+
+    /// A square wooden crate.
+    pub fn crate_at(pos: [f32; 2]) -> Self {
+        const SIZE: usize = 14;
+        Self::new(
+            pos,
+            SIZE,
+            SIZE,
+            vec![true; SIZE * SIZE],
+            [0.55, 0.35, 0.15, 1.0],
+        )
+        .unwrap()
+    }
+
+    /// A roughly round rock, whose outline is varied by `seed`.
+    pub fn rock_at(pos: [f32; 2], seed: u32) -> Self {
+        const SIZE: usize = 16;
+        let radius = SIZE as f32 / 2.0;
+        let solid = (0..SIZE * SIZE)
+            .map(|i| {
+                let x = (i % SIZE) as f32 + 0.5 - radius;
+                let y = (i / SIZE) as f32 + 0.5 - radius;
+                // Bumps along the outline, picked per eighth of a turn.
+                let sector =
+                    ((y.atan2(x) + std::f32::consts::PI) * 4.0 / std::f32::consts::PI) as u32;
+                let bump = hash(seed ^ sector) as f32 / u32::MAX as f32;
+                x.hypot(y) < radius * (0.75 + 0.25 * bump)
+            })
+            .collect();
+        Self::new(pos, SIZE, SIZE, solid, [0.45, 0.45, 0.5, 1.0]).unwrap()
+    }
+
+    /// Advances the body by `delta_time`, colliding it with the solid edges of the world and the
+    /// cells of `obstacles`, and wrapping it around wrapping edges. Returns the fastest impact
+    /// speed along a contact normal.
+    pub fn step(
+        &mut self,
+        delta_time: f32,
+        gravity: [f32; 2],
+        settings: &BodySettings,
+        boundaries: &Boundaries,
+        obstacles: &ObstacleMask,
+    ) -> f32 {
+        // Keep every cell moving at most half a cell per substep.
+        let radius = (self.width.max(self.height) as f32) * CELL_SIZE;
+        let speed = self.vel[0].hypot(self.vel[1]) + self.angular_vel.abs() * radius;
+        let substeps =
+            ((speed * delta_time / (0.5 * CELL_SIZE)).ceil() as u32).clamp(SUBSTEPS, MAX_SUBSTEPS);
+        let dt = delta_time / substeps as f32;
+        let mut impact: f32 = 0.0;
+
+        for _ in 0..substeps {
+            self.vel = [self.vel[0] + gravity[0] * dt, self.vel[1] + gravity[1] * dt];
+            self.pos = [
+                self.pos[0] + self.vel[0] * dt,
+                self.pos[1] + self.vel[1] * dt,
+            ];
+            self.angle += self.angular_vel * dt;
+            self.wrap(boundaries);
+
+            let mut contacts = self.edge_contacts(boundaries);
+            contacts.extend(self.cell_contacts(obstacles));
+            for contact in &contacts {
+                impact = impact.max(self.resolve(contact, settings));
+            }
+
+            // Push the body out along each normal by the deepest penetration along it.
+            for normal in [[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]] {
+                let depth = contacts
+                    .iter()
+                    .filter(|c| c.normal == normal)
+                    .map(|c| c.depth)
+                    .fold(0.0, f32::max);
+                self.pos = [
+                    self.pos[0] + normal[0] * depth,
+                    self.pos[1] + normal[1] * depth,
+                ];
+            }
+        }
+
+        impact
+    }
+
+    /// Pushes the body away from an explosion at `center`, as hard as the particles there.
+    pub fn blast(&mut self, center: [f32; 2], radius: f32, strength: f32) {
+        let d = [self.pos[0] - center[0], self.pos[1] - center[1]];
+        let dist = d[0].hypot(d[1]);
+        if dist >= radius {
+            return;
+        }
+
+        let power = strength * (1.0 - dist / radius);
+        let dir = if dist > 1e-6 {
+            [d[0] / dist, d[1] / dist]
+        } else {
+            [0.0, -1.0]
+        };
+        self.vel = [self.vel[0] + dir[0] * power, self.vel[1] + dir[1] * power];
+    }
+
+    /// Calls `emit` with every obstacle cell the body covers.
+    pub fn rasterize(&self, mut emit: impl FnMut(usize, usize)) {
+        let (sin, cos) = self.angle.sin_cos();
+        let extent = (self.width.max(self.height) as f32) * CELL_SIZE;
+        let first = |c: f32| (((c - extent + 1.0) / CELL_SIZE).floor().max(0.0)) as usize;
+        let last =
+            |c: f32| (((c + extent + 1.0) / CELL_SIZE).ceil() as usize).min(OBSTACLE_GRID_SIZE - 1);
+
+        for y in first(self.pos[1])..=last(self.pos[1]) {
+            for x in first(self.pos[0])..=last(self.pos[0]) {
+                // Map the cell center back into body space.
+                let [cx, cy] = cell_center(x, y);
+                let (dx, dy) = (cx - self.pos[0], cy - self.pos[1]);
+                let local_x = (dx * cos + dy * sin) / CELL_SIZE + self.center[0];
+                let local_y = (-dx * sin + dy * cos) / CELL_SIZE + self.center[1];
+                if self.is_solid(local_x, local_y) {
+                    emit(x, y);
+                }
+            }
+        }
+    }
+
+    /// Positions and velocities of the loose particles the body turns into when it shatters.
+    pub fn fragments(&self) -> impl Iterator<Item = ([f32; 2], [f32; 2])> + '_ {
+        self.world_cells().map(|(point, r)| {
+            let vel = [
+                self.vel[0] - self.angular_vel * r[1],
+                self.vel[1] + self.angular_vel * r[0],
+            ];
+            (point, vel)
+        })
+    }
+
+    fn is_solid(&self, x: f32, y: f32) -> bool {
+        if x < 0.0 || y < 0.0 {
+            return false;
+        }
+        let (x, y) = (x as usize, y as usize);
+        x < self.width && y < self.height && self.solid[y * self.width + x]
+    }
+
+    // World positions of every cell center, along with their offset from the center of mass.
+    fn world_cells(&self) -> impl Iterator<Item = ([f32; 2], [f32; 2])> + '_ {
+        let (sin, cos) = self.angle.sin_cos();
+        (0..self.width * self.height)
+            .filter(|&i| self.solid[i])
+            .map(move |i| {
+                let lx = ((i % self.width) as f32 + 0.5 - self.center[0]) * CELL_SIZE;
+                let ly = ((i / self.width) as f32 + 0.5 - self.center[1]) * CELL_SIZE;
+                let r = [lx * cos - ly * sin, lx * sin + ly * cos];
+                ([self.pos[0] + r[0], self.pos[1] + r[1]], r)
+            })
+    }
+
+    // Whether every cell of the body is outside the world, which it can only get to through
+    // open edges.
+    fn has_left(&self) -> bool {
+        self.world_cells()
+            .all(|(point, _)| point[0].abs() > 1.0 || point[1].abs() > 1.0)
+    }
+
+    // Moves the body to the opposite side once its center of mass crosses a wrapping edge.
+    fn wrap(&mut self, boundaries: &Boundaries) {
+        let edges = [
+            (0, -1.0, boundaries.left),
+            (0, 1.0, boundaries.right),
+            (1, -1.0, boundaries.top),
+            (1, 1.0, boundaries.bottom),
+        ];
+        for (axis, side, edge) in edges {
+            if edge == Boundary::Wrap && self.pos[axis] * side > 1.0 {
+                self.pos[axis] -= 2.0 * side;
+            }
+        }
+    }
+
+    // Contacts of the cells past the solid and bouncy edges of the world.
+    fn edge_contacts(&self, boundaries: &Boundaries) -> Vec<Contact> {
+        let solid = |edge| matches!(edge, Boundary::Solid | Boundary::Bouncy { .. });
+        let mut contacts = Vec::new();
+        for (point, _) in self.world_cells() {
+            let [x, y] = point;
+            let mut push = |edge, normal, depth: f32| {
+                if solid(edge) && depth > 0.0 {
+                    contacts.push(Contact {
+                        point,
+                        normal,
+                        depth,
+                    })
+                }
+            };
+            push(boundaries.left, [1.0, 0.0], -1.0 - x);
+            push(boundaries.right, [-1.0, 0.0], x - 1.0);
+            push(boundaries.top, [0.0, 1.0], -1.0 - y);
+            push(boundaries.bottom, [0.0, -1.0], y - 1.0);
+        }
+        contacts
+    }
+
+    // Contacts of the cells overlapping a set cell of `obstacles`. Each pushes out of the
+    // obstacle cell the shortest way that doesn't lead into another one. Cells buried deeper
+    // have no way out and are left to the cells on the surface.
+    fn cell_contacts(&self, obstacles: &ObstacleMask) -> Vec<Contact> {
+        let is_set = |pos| cell_of(pos).is_some_and(|(x, y)| obstacles.is_set(x, y));
+        let normals = [[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]];
+        let mut contacts = Vec::new();
+        for (point, _) in self.world_cells() {
+            let Some((x, y)) = cell_of(point).filter(|&(x, y)| obstacles.is_set(x, y)) else {
+                continue;
+            };
+            let center = cell_center(x, y);
+            let d = [point[0] - center[0], point[1] - center[1]];
+            let way_out = normals
+                .into_iter()
+                .filter(|n| !is_set([center[0] + n[0] * CELL_SIZE, center[1] + n[1] * CELL_SIZE]))
+                .map(|n| (n, CELL_SIZE - (n[0] * d[0] + n[1] * d[1])))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((normal, depth)) = way_out {
+                contacts.push(Contact {
+                    point,
+                    normal,
+                    depth,
+                });
+            }
+        }
+        contacts
+    }
+
+    // Applies the collision and friction impulse of one contact, returning the speed the body
+    // was moving into it with.
+    fn resolve(&mut self, contact: &Contact, settings: &BodySettings) -> f32 {
+        let r = [
+            contact.point[0] - self.pos[0],
+            contact.point[1] - self.pos[1],
+        ];
+        let n = contact.normal;
+        let point_vel = |body: &Self| {
+            [
+                body.vel[0] - body.angular_vel * r[1],
+                body.vel[1] + body.angular_vel * r[0],
+            ]
+        };
+        let cross = |a: [f32; 2], b: [f32; 2]| a[0] * b[1] - a[1] * b[0];
+        let dot = |a: [f32; 2], b: [f32; 2]| a[0] * b[0] + a[1] * b[1];
+
+        let normal_speed = dot(point_vel(self), n);
+        if normal_speed >= 0.0 {
+            return 0.0;
+        }
+
+        let rn = cross(r, n);
+        let jn = -(1.0 + settings.restitution) * normal_speed
+            / (self.inv_mass + self.inv_inertia * rn * rn);
+        self.apply_impulse(r, [n[0] * jn, n[1] * jn]);
+
+        // Coulomb friction along the surface.
+        let t = [-n[1], n[0]];
+        let rt = cross(r, t);
+        let jt = (-dot(point_vel(self), t) / (self.inv_mass + self.inv_inertia * rt * rt))
+            .clamp(-settings.friction * jn, settings.friction * jn);
+        self.apply_impulse(r, [t[0] * jt, t[1] * jt]);
+
+        -normal_speed
+    }
+
+    fn apply_impulse(&mut self, r: [f32; 2], impulse: [f32; 2]) {
+        self.vel[0] += impulse[0] * self.inv_mass;
+        self.vel[1] += impulse[1] * self.inv_mass;
+        self.angular_vel += (r[0] * impulse[1] - r[1] * impulse[0]) * self.inv_inertia;
+    }
+}
+
+/// Steps every body, colliding it with `walls` and the other bodies. Removes the bodies that left
+/// through an open edge, and the ones that hit something hard enough to shatter, returning the
+/// latter.
+pub fn step_bodies(
+    bodies: &mut Vec<RigidBody>,
+    delta_time: f32,
+    gravity: [f32; 2],
+    settings: &BodySettings,
+    boundaries: &Boundaries,
+    walls: &ObstacleMask,
+) -> Vec<RigidBody> {
+    let mut shattered = Vec::new();
+    let mut i = 0;
+    while i < bodies.len() {
+        // The other bodies are held still while this one moves.
+        let mut obstacles = walls.clone();
+        for (_, other) in bodies.iter().enumerate().filter(|&(j, _)| j != i) {
+            other.rasterize(|x, y| obstacles.set(x, y));
+        }
+
+        let impact = bodies[i].step(delta_time, gravity, settings, boundaries, &obstacles);
+        if impact > settings.shatter_speed {
+            shattered.push(bodies.swap_remove(i));
+        } else if bodies[i].has_left() {
+            bodies.swap_remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    shattered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_comes_to_rest_on_the_floor() {
+        let settings = BodySettings::default();
+        let mut body = RigidBody::crate_at([0.0, 0.9]);
+        for _ in 0..600 {
+            body.step(
+                1.0 / 60.0,
+                [0.0, 9.8],
+                &settings,
+                &Boundaries::default(),
+                &ObstacleMask::new(),
+            );
+        }
+
+        // Resting on the floor at y = 1, with half of its 14 cells below its center.
+        let half = 7.0 * CELL_SIZE;
+        assert!(
+            (body.pos[1] - (1.0 - half)).abs() < CELL_SIZE,
+            "{:?}",
+            body.pos
+        );
+        assert!(body.vel[1].abs() < 0.1);
+    }
+
+    #[test]
+    fn hard_impact_shatters() {
+        let settings = BodySettings::default();
+        let mut body = RigidBody::crate_at([0.0, 0.9]);
+        body.vel = [0.0, 2.0 * settings.shatter_speed];
+        let mut bodies = vec![body];
+
+        let shattered = step_bodies(
+            &mut bodies,
+            1.0 / 60.0,
+            [0.0, 9.8],
+            &settings,
+            &Boundaries::default(),
+            &ObstacleMask::new(),
+        );
+
+        assert!(bodies.is_empty());
+        assert_eq!(shattered[0].fragments().count(), 14 * 14);
+    }
+
+    #[test]
+    fn body_comes_to_rest_on_a_wall() {
+        let settings = BodySettings::default();
+        let mut walls = ObstacleMask::new();
+        let row = OBSTACLE_GRID_SIZE / 2;
+        for x in 0..OBSTACLE_GRID_SIZE {
+            walls.set(x, row);
+        }
+        let mut bodies = vec![RigidBody::crate_at([0.0, -0.2])];
+        for _ in 0..600 {
+            step_bodies(
+                &mut bodies,
+                1.0 / 60.0,
+                [0.0, 9.8],
+                &settings,
+                &Boundaries::default(),
+                &walls,
+            );
+        }
+
+        // The top of the wall is at y = 0.
+        let half = 7.0 * CELL_SIZE;
+        assert!(
+            (bodies[0].pos[1] + half).abs() < CELL_SIZE,
+            "{:?}",
+            bodies[0].pos
+        );
+    }
+
+    #[test]
+    fn bodies_dont_overlap() {
+        let settings = BodySettings::default();
+        let floor = 1.0 - 7.0 * CELL_SIZE;
+        let mut bodies = vec![
+            RigidBody::crate_at([0.0, floor]),
+            RigidBody::crate_at([0.0, floor - 0.1]),
+        ];
+        for _ in 0..600 {
+            step_bodies(
+                &mut bodies,
+                1.0 / 60.0,
+                [0.0, 9.8],
+                &settings,
+                &Boundaries::default(),
+                &ObstacleMask::new(),
+            );
+        }
+
+        let mut cells = ObstacleMask::new();
+        bodies[0].rasterize(|x, y| cells.set(x, y));
+        let mut shared = 0;
+        bodies[1].rasterize(|x, y| shared += cells.is_set(x, y) as u32);
+        assert_eq!(shared, 0, "{:?}", [bodies[0].pos, bodies[1].pos]);
+    }
+
+    #[test]
+    fn body_falls_through_an_open_floor() {
+        let settings = BodySettings::default();
+        let boundaries = Boundaries {
+            bottom: Boundary::Open,
+            ..Boundaries::default()
+        };
+        let mut bodies = vec![RigidBody::crate_at([0.0, 0.9])];
+        for _ in 0..60 {
+            let shattered = step_bodies(
+                &mut bodies,
+                1.0 / 60.0,
+                [0.0, 9.8],
+                &settings,
+                &boundaries,
+                &ObstacleMask::new(),
+            );
+            assert!(shattered.is_empty());
+        }
+
+        assert!(bodies.is_empty());
+    }
+
+    #[test]
+    fn body_wraps_around() {
+        let settings = BodySettings::default();
+        let boundaries = Boundaries {
+            left: Boundary::Wrap,
+            right: Boundary::Wrap,
+            ..Boundaries::default()
+        };
+        let mut body = RigidBody::crate_at([0.99, 0.0]);
+        body.vel = [1.0, 0.0];
+        body.step(
+            0.1,
+            [0.0, 0.0],
+            &settings,
+            &boundaries,
+            &ObstacleMask::new(),
+        );
+
+        assert!((body.pos[0] + 0.91).abs() < 1e-4, "{:?}", body.pos);
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    material::MATERIAL_ID_BITS,
+    obstacles::{ObstacleMask, OBSTACLE_GRID_SIZE},
+    Material, MyVertex,
+};
+
+pub const DEFAULT_SAVE_PATH: &str = "sand.sav";
+
+const MAGIC: &[u8; 4] = b"SAND";
+const VERSION: u32 = 1;
+
+/// Everything needed to restore the world: the walls and every particle. Saved as a small binary
+/// file, all values little endian, or as JSON when the file name ends in `.json`.
+pub struct Snapshot {
+    pub walls: ObstacleMask,
+    pub particles: Vec<MyVertex>,
+}
+
+// The JSON form of a snapshot, listing the wall cells and naming each particle's material.
+#[derive(Serialize, Deserialize)]
+struct JsonSnapshot {
+    walls: Vec<[usize; 2]>,
+    particles: Vec<JsonParticle>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonParticle {
+    pos: [f32; 2],
+    vel: [f32; 2],
+    material: Material,
+    shade: u32,
+    heat: f32,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        if is_json(path) {
+            serde_json::to_writer(&mut writer, &self.to_json()?)?;
+        } else {
+            self.write_to(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(File::open(path)?);
+        if is_json(path) {
+            Self::from_json(serde_json::from_reader(reader)?)
+        } else {
+            Self::read_from(&mut reader)
+        }
+    }
+
+    fn to_json(&self) -> io::Result<JsonSnapshot> {
+        let particles = self
+            .particles
+            .iter()
+            .map(|p| {
+                let id = p.material & ((1 << MATERIAL_ID_BITS) - 1);
+                let material = Material::from_id(id).ok_or_else(|| {
+                    io::Error::new(ErrorKind::InvalidData, format!("unknown material {id}"))
+                })?;
+                Ok(JsonParticle {
+                    pos: p.pos,
+                    vel: p.vel,
+                    material,
+                    shade: p.material >> MATERIAL_ID_BITS,
+                    heat: p.heat,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(JsonSnapshot {
+            walls: self.walls.cells().map(|(x, y)| [x, y]).collect(),
+            particles,
+        })
+    }
+
+    fn from_json(json: JsonSnapshot) -> io::Result<Self> {
+        let mut walls = ObstacleMask::new();
+        for [x, y] in json.walls {
+            if x >= OBSTACLE_GRID_SIZE || y >= OBSTACLE_GRID_SIZE {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("wall cell {x}, {y} is outside the world"),
+                ));
+            }
+            walls.set(x, y);
+        }
+        let particles = json
+            .particles
+            .into_iter()
+            .map(|p| MyVertex {
+                pos: p.pos,
+                vel: p.vel,
+                material: p.material.id() | p.shade << MATERIAL_ID_BITS,
+                heat: p.heat,
+            })
+            .collect();
+        Ok(Self { walls, particles })
+    }
+
+    fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+
+        for word in self.walls.words() {
+            w.write_all(&word.to_le_bytes())?;
+        }
+
+        w.write_all(&(self.particles.len() as u32).to_le_bytes())?;
+        for p in &self.particles {
+            for value in [p.pos[0], p.pos[1], p.vel[0], p.vel[1]] {
+                w.write_all(&value.to_le_bytes())?;
+            }
+            w.write_all(&p.material.to_le_bytes())?;
+            w.write_all(&p.heat.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read_from(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a save file"));
+        }
+        let version = read_u32(r)?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported save version {version}"),
+            ));
+        }
+
+        let words = (0..ObstacleMask::new().words().len())
+            .map(|_| read_u32(r))
+            .collect::<io::Result<_>>()?;
+        let walls = ObstacleMask::from_words(words).unwrap();
+
+        let count = read_u32(r)?;
+        let particles = (0..count)
+            .map(|_| {
+                Ok(MyVertex {
+                    pos: [read_f32(r)?, read_f32(r)?],
+                    vel: [read_f32(r)?, read_f32(r)?],
+                    material: read_u32(r)?,
+                    heat: read_f32(r)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { walls, particles })
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    read_u32(r).map(f32::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Material, Rng};
+
+    #[test]
+    fn round_trips() {
+        let mut walls = ObstacleMask::new();
+        walls.set(3, 7);
+        let snapshot = Snapshot {
+            walls,
+            particles: vec![MyVertex::new(
+                [0.25, -0.5],
+                [1.0, 2.0],
+                Material::Fire,
+                &mut Rng::default(),
+            )],
+        };
+
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        let loaded = Snapshot::read_from(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.walls, snapshot.walls);
+        let (a, b) = (&loaded.particles[0], &snapshot.particles[0]);
+        assert_eq!(
+            (a.pos, a.vel, a.material, a.heat),
+            (b.pos, b.vel, b.material, b.heat)
+        );
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let mut walls = ObstacleMask::new();
+        walls.set(3, 7);
+        let snapshot = Snapshot {
+            walls,
+            particles: vec![MyVertex::new(
+                [0.25, -0.5],
+                [1.0, 2.0],
+                Material::Smoke,
+                &mut Rng::default(),
+            )],
+        };
+
+        let json = serde_json::to_string(&snapshot.to_json().unwrap()).unwrap();
+        let loaded = Snapshot::from_json(serde_json::from_str(&json).unwrap()).unwrap();
+
+        assert_eq!(loaded.walls, snapshot.walls);
+        let (a, b) = (&loaded.particles[0], &snapshot.particles[0]);
+        assert_eq!(
+            (a.pos, a.vel, a.material, a.heat),
+            (b.pos, b.vel, b.material, b.heat)
+        );
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let result = Snapshot::read_from(&mut b"not a save".as_slice());
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{error::Error, fs, mem, path::PathBuf, sync::Arc};
+
+use shaderc::{CompileOptions, Compiler, ResolvedInclude, ShaderKind};
+use tracing::{error, info, warn};
+use vulkano::{
+    device::Device,
+    shader::{EntryPoint, ShaderModule, ShaderModuleCreateInfo},
+};
+
+use super::watch::FileWatcher;
+
+// The shaders that can be reloaded, by the files in `shaders/` they are compiled from.
+const SIMULATE: &str = "simulate.comp";
+const PARTICLES_VS: &str = "particles.vert";
+const PARTICLES_FS: &str = "particles.frag";
+const SOLID_VS: &str = "solid.vert";
+// Files the shaders include, from the same directory.
+const INCLUDES: [&str; 2] = ["sim_params.glsl", "material.glsl"];
+
+/// Shaders compiled at runtime, in place of `cs`, `vs`, `fs` and `solid_vs`.
+pub struct Shaders {
+    pub cs: EntryPoint,
+    pub vs: EntryPoint,
+    pub fs: EntryPoint,
+    pub solid_vs: EntryPoint,
+}
+
+/// Compiles the simulation and particle shaders from the GLSL files in a directory, again
+/// whenever one of them changes. Meant for tuning them without rebuilding the app.
+pub struct ShaderReloader {
+    compiler: Compiler,
+    dir: PathBuf,
+    watcher: FileWatcher,
+    // Whether to compile on the next poll even if nothing changed.
+    pending: bool,
+}
+
+impl ShaderReloader {
+    /// Returns `None` if the shader compiler can't be created.
+    pub fn new(dir: PathBuf) -> Option<Self> {
+        let files = [SIMULATE, PARTICLES_VS, PARTICLES_FS, SOLID_VS]
+            .into_iter()
+            .chain(INCLUDES)
+            .map(|file| dir.join(file))
+            .collect::<Vec<_>>();
+        Some(Self {
+            compiler: Compiler::new()?,
+            dir,
+            watcher: FileWatcher::new(files),
+            pending: true,
+        })
+    }
+
+    /// Compiles the shaders on the next poll, changed or not, such as for a new device.
+    pub fn reload(&mut self) {
+        self.pending = true;
+    }
+
+    /// Returns the shaders compiled anew if any of them changed, or `None` if nothing changed or
+    /// one of them failed to compile, which is logged.
+    pub fn poll(&mut self, device: &Arc<Device>) -> Option<Shaders> {
+        let changed = self.watcher.changed() | mem::take(&mut self.pending);
+        if !changed {
+            return None;
+        }
+
+        match self.compile_all(device) {
+            Ok(shaders) => {
+                info!("compiled shaders from {}", self.dir.display());
+                Some(shaders)
+            }
+            Err(e) => {
+                error!("{e}, keeping the previous shaders");
+                None
+            }
+        }
+    }
+
+    fn compile_all(&mut self, device: &Arc<Device>) -> Result<Shaders, Box<dyn Error>> {
+        Ok(Shaders {
+            cs: self.compile(device, SIMULATE, ShaderKind::Compute)?,
+            vs: self.compile(device, PARTICLES_VS, ShaderKind::Vertex)?,
+            fs: self.compile(device, PARTICLES_FS, ShaderKind::Fragment)?,
+            solid_vs: self.compile(device, SOLID_VS, ShaderKind::Vertex)?,
+        })
+    }
+
+    fn compile(
+        &mut self,
+        device: &Arc<Device>,
+        file: &str,
+        kind: ShaderKind,
+    ) -> Result<EntryPoint, Box<dyn Error>> {
+        let path = self.dir.join(file);
+        let source = fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let mut options = CompileOptions::new().ok_or("failed to create the compile options")?;
+        let dir = &self.dir;
+        options.set_include_callback(|name, _, _, _| {
+            let path = dir.join(name);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            Ok(ResolvedInclude {
+                resolved_name: path.display().to_string(),
+                content,
+            })
+        });
+        let artifact = self
+            .compiler
+            .compile_into_spirv(&source, kind, file, "main", Some(&options))
+            .map_err(|e| format!("failed to compile {file}: {e}"))?;
+        if artifact.get_num_warnings() > 0 {
+            warn!("{file}: {}", artifact.get_warning_messages());
+        }
+
+        // The compiler only outputs valid SPIR-V.
+        let module = unsafe {
+            ShaderModule::new(
+                device.clone(),
+                ShaderModuleCreateInfo::new(artifact.as_binary()),
+            )
+        }?;
+        Ok(module.entry_point("main").unwrap())
+    }
+}
@@ -3,55 +3,349 @@
 pub mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
+        path: "shaders/particles.vert",
+        include: ["shaders"],
+    }
+}
+
+// The fragment shader will only need to apply the color forwarded by the vertex shader,
+// because the color of a particle should be identical over all pixels.
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/particles.frag",
+    }
+}
+
+// Vertex shader for the triangles of solid obstacles, which are drawn in a flat color.
+pub mod solid_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/solid.vert",
+    }
+}
+
+// Compute shader for updating the position and velocity of each particle every frame.
+pub mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/simulate.comp",
+        include: ["shaders"],
+    }
+}
+
+// Compute shader that packs the particles still alive to the front of a scratch buffer, so
+// particles removed by `cs` can be dropped from the vertex buffer.
+pub mod compact_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
         src: r"
             #version 450
 
-            layout(location = 0) in vec2 pos;
-            layout(location = 1) in vec2 vel;
+            layout(local_size_x = 1024, local_size_y = 1, local_size_z = 1) in;
 
-            layout(location = 0) out vec4 outColor;
+            struct VertexData {
+                vec2 pos;
+                vec2 vel;
+                uint material;
+                float heat;
+            };
+
+            layout (binding = 0) readonly buffer SourceBuffer {
+                VertexData src[];
+            };
+
+            layout (binding = 1) writeonly buffer TargetBuffer {
+                VertexData dst[];
+            };
 
-            // Keep this value in sync with the `maxSpeed` const in the compute shader.
-            const float maxSpeed = 10.0;
+            layout (binding = 2) buffer Counter {
+                uint alive_count;
+            };
+
+            layout (push_constant) uniform PushConstants {
+                uint particle_count;
+            } push;
+
+            // Keep in sync with `DEAD` in `cs`.
+            const float DEAD = 1.0e6;
 
             void main() {
-                gl_Position = vec4(pos, 0.0, 1.0);
-                gl_PointSize = 1.0;
+                const uint index = gl_GlobalInvocationID.x;
+
+                if (index >= push.particle_count || src[index].pos.x >= DEAD) {
+                    return;
+                }
 
-                // Mix colors based on position and velocity.
-                outColor = mix(
-                    0.2 * vec4(pos, abs(vel.x) + abs(vel.y), 1.0),
-                    vec4(1.0, 0.5, 0.8, 1.0),
-                    sqrt(length(vel) / maxSpeed)
-                );
+                dst[atomicAdd(alive_count, 1)] = src[index];
             }
         ",
     }
 }
 
-// The fragment shader will only need to apply the color forwarded by the vertex shader,
-// because the color of a particle should be identical over all pixels.
-pub mod fs {
+// Compute shader sorting particles into the cells of a spatial hash, and using it for
+// interactions between neighbouring particles: separating particles that overlap, and the density
+// and force passes of SPH fluids. It runs as a sequence of dispatches of the same pipeline,
+// `stage` selecting which step each one performs.
+pub mod neighbour_cs {
     vulkano_shaders::shader! {
-        ty: "fragment",
+        ty: "compute",
         src: r"
             #version 450
 
-            layout(location = 0) in vec4 outColor;
+            layout(local_size_x = 1024, local_size_y = 1, local_size_z = 1) in;
 
-            layout(location = 0) out vec4 fragColor;
+            struct VertexData {
+                vec2 pos;
+                vec2 vel;
+                uint material;
+                float heat;
+            };
+
+            layout (binding = 0) buffer VertexBuffer {
+                VertexData vertices[];
+            };
+
+            // Copy of the particles ordered by cell, and the index each one came from.
+            layout (binding = 1) buffer SortedBuffer {
+                VertexData sorted[];
+            };
+
+            layout (binding = 2) buffer SortedIds {
+                uint sorted_ids[];
+            };
+
+            // Position of each particle within its cell.
+            layout (binding = 3) buffer Ranks {
+                uint ranks[];
+            };
+
+            layout (binding = 4) buffer CellCounts {
+                uint counts[];
+            };
+
+            // Exclusive prefix sum of `counts`, where each cell's particles start in `sorted`.
+            layout (binding = 5) buffer CellStarts {
+                uint starts[];
+            };
+
+            layout (binding = 6) buffer BlockSums {
+                uint block_sums[];
+            };
+
+            // SPH density of each particle, indexed like `vertices`.
+            layout (binding = 7) buffer Densities {
+                float densities[];
+            };
+
+            // `cell_size` is the particle diameter when colliding, and the smoothing radius of
+            // the SPH kernels otherwise.
+            layout (push_constant) uniform PushConstants {
+                uint stage;
+                uint particle_count;
+                float cell_size;
+                float delta_time;
+                float rest_density;
+                float stiffness;
+                float viscosity;
+                float particle_mass;
+            } push;
+
+            // Keep the stages and table size in sync with `spatial_hash.rs`.
+            const uint STAGE_COUNT = 0;
+            const uint STAGE_SCAN_BLOCKS = 1;
+            const uint STAGE_SCAN_SUMS = 2;
+            const uint STAGE_ADD_OFFSETS = 3;
+            const uint STAGE_SCATTER = 4;
+            const uint STAGE_COLLIDE = 5;
+            const uint STAGE_DENSITY = 6;
+            const uint STAGE_FORCES = 7;
+
+            const uint TABLE_SIZE = 1048576;
+
+            // Keep in sync with `DEAD` in `cs`.
+            const float DEAD = 1.0e6;
+
+            // Bounds the work done per particle in very dense areas.
+            const uint MAX_NEIGHBOURS = 64;
+
+            const float PI = 3.14159265;
+
+            shared uint scan_data[1024];
+
+            // All interacting particles are within one cell size, so they are in the 3x3 block
+            // of cells around a particle.
+            ivec2 cell_of(vec2 pos) {
+                return ivec2(floor(pos / push.cell_size));
+            }
+
+            uint cell_hash(ivec2 cell) {
+                return ((uint(cell.x) * 73856093u) ^ (uint(cell.y) * 19349663u)) & (TABLE_SIZE - 1u);
+            }
+
+            bool is_alive(uint index) {
+                return index < push.particle_count && vertices[index].pos.x < DEAD;
+            }
+
+            // Exclusive scan of `value` across the workgroup, the sum of all values is written
+            // to `total`. Must be reached by every invocation of the workgroup.
+            uint workgroup_scan(uint value, out uint total) {
+                const uint lid = gl_LocalInvocationID.x;
+
+                scan_data[lid] = value;
+                barrier();
+
+                for (uint offset = 1; offset < 1024; offset *= 2) {
+                    uint add = lid >= offset ? scan_data[lid - offset] : 0u;
+                    barrier();
+                    scan_data[lid] += add;
+                    barrier();
+                }
+
+                total = scan_data[1023];
+                return scan_data[lid] - value;
+            }
+
+            // Direction to separate two particles at the exact same position, opposite for
+            // each of the two.
+            vec2 pair_direction(uint a, uint b) {
+                uint h = (min(a, b) * 2654435761u) ^ (max(a, b) * 40503u);
+                float angle = float(h & 0xffffu) * (2.0 * PI / 65536.0);
+                vec2 n = vec2(cos(angle), sin(angle));
+                return a < b ? n : -n;
+            }
+
+            // 2D SPH kernels of Mueller et al. with smoothing radius `h`.
+            float poly6(float r, float h) {
+                float d = h * h - r * r;
+                return 4.0 / (PI * pow(h, 8.0)) * d * d * d;
+            }
+
+            float spiky_gradient(float r, float h) {
+                return -30.0 / (PI * pow(h, 5.0)) * (h - r) * (h - r);
+            }
+
+            float viscosity_laplacian(float r, float h) {
+                return 40.0 / (PI * pow(h, 5.0)) * (h - r);
+            }
+
+            float pressure(float density) {
+                // Only push particles apart, pulling them together makes the fluid clump.
+                return max(push.stiffness * (density - push.rest_density), 0.0);
+            }
+
+            // Accumulate the interaction of the current stage with every neighbour within one
+            // cell size, and store the result.
+            void interact(uint index) {
+                vec2 pos = vertices[index].pos;
+                vec2 vel = vertices[index].vel;
+                float h = push.cell_size;
+                ivec2 home = cell_of(pos);
+
+                // A particle contributes to its own density.
+                float density = push.particle_mass * poly6(0.0, h);
+                float own_density = push.stage == STAGE_FORCES ? densities[index] : 1.0;
+                float own_pressure = pressure(own_density);
+                vec2 accel = vec2(0.0);
+                vec2 correction = vec2(0.0);
+                vec2 impulse = vec2(0.0);
+                uint checked = 0;
+
+                for (int y = -1; y <= 1; y++) {
+                    for (int x = -1; x <= 1; x++) {
+                        uint cell = cell_hash(home + ivec2(x, y));
+                        uint end = starts[cell] + counts[cell];
+
+                        for (uint s = starts[cell]; s < end && checked < MAX_NEIGHBOURS; s++) {
+                            uint other = sorted_ids[s];
+                            if (other == index) {
+                                continue;
+                            }
+                            checked++;
+
+                            vec2 delta = pos - sorted[s].pos;
+                            float dist = length(delta);
+                            if (dist >= h) {
+                                continue;
+                            }
+                            vec2 n = dist > 1.0e-6 ? delta / dist : pair_direction(index, other);
+
+                            if (push.stage == STAGE_COLLIDE) {
+                                // Each particle of the pair moves away by half the overlap, and
+                                // loses half of the velocity they approach each other with.
+                                correction += 0.5 * (h - dist) * n;
+
+                                float approach = dot(vel - sorted[s].vel, n);
+                                if (approach < 0.0) {
+                                    impulse -= 0.5 * approach * n;
+                                }
+                            } else if (push.stage == STAGE_DENSITY) {
+                                density += push.particle_mass * poly6(dist, h);
+                            } else {
+                                float other_density = densities[other];
+                                float shared_pressure = 0.5 * (own_pressure + pressure(other_density));
+                                accel -= push.particle_mass * shared_pressure / other_density
+                                    * spiky_gradient(dist, h) * n;
+                                accel += push.viscosity * push.particle_mass
+                                    * (sorted[s].vel - vel) / other_density
+                                    * viscosity_laplacian(dist, h);
+                            }
+                        }
+                    }
+                }
+
+                if (push.stage == STAGE_COLLIDE) {
+                    vertices[index].pos = pos + correction;
+                    vertices[index].vel = vel + impulse;
+                } else if (push.stage == STAGE_DENSITY) {
+                    densities[index] = density;
+                } else {
+                    vertices[index].vel = vel + push.delta_time * accel / own_density;
+                }
+            }
 
             void main() {
-                fragColor = outColor;
+                const uint index = gl_GlobalInvocationID.x;
+
+                if (push.stage == STAGE_COUNT) {
+                    if (is_alive(index)) {
+                        uint cell = cell_hash(cell_of(vertices[index].pos));
+                        ranks[index] = atomicAdd(counts[cell], 1);
+                    }
+                } else if (push.stage == STAGE_SCAN_BLOCKS) {
+                    // Dispatched once per 1024 cells, each workgroup scans its block.
+                    uint total;
+                    starts[index] = workgroup_scan(counts[index], total);
+                    if (gl_LocalInvocationID.x == 0) {
+                        block_sums[gl_WorkGroupID.x] = total;
+                    }
+                } else if (push.stage == STAGE_SCAN_SUMS) {
+                    // Dispatched as a single workgroup, there are exactly 1024 block sums.
+                    uint total;
+                    block_sums[index] = workgroup_scan(block_sums[index], total);
+                } else if (push.stage == STAGE_ADD_OFFSETS) {
+                    starts[index] += block_sums[gl_WorkGroupID.x];
+                } else if (push.stage == STAGE_SCATTER) {
+                    if (is_alive(index)) {
+                        uint cell = cell_hash(cell_of(vertices[index].pos));
+                        uint slot = starts[cell] + ranks[index];
+                        sorted[slot] = vertices[index];
+                        sorted_ids[slot] = index;
+                    }
+                } else if (is_alive(index)) {
+                    interact(index);
+                }
             }
         ",
     }
 }
 
-// Compute shader for updating the position and velocity of each particle every frame.
-pub mod cs {
+// Compute shader writing the color of every particle into the cell it is in, for the grid render
+// mode. Particles are colored the same way as in `vs`.
+pub mod grid_cs {
     vulkano_shaders::shader! {
         ty: "compute",
+        include: ["shaders"],
         src: r"
             #version 450
 
@@ -60,63 +354,386 @@ pub mod cs {
             struct VertexData {
                 vec2 pos;
                 vec2 vel;
+                uint material;
+                float heat;
             };
 
-            // Storage buffer binding, which we optimize by using a DeviceLocalBuffer.
-            layout (binding = 0) buffer VertexBuffer {
+            layout(set = 0, binding = 0) readonly buffer VertexBuffer {
                 VertexData vertices[];
             };
 
-            // Allow push constants to define parameters of compute.
-            layout (push_constant) uniform PushConstants {
-                float delta_time;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D grid;
+
+            #define SIM_PARAMS_SET 0
+            #define SIM_PARAMS_BINDING 2
+            #include <sim_params.glsl>
+            #include <material.glsl>
+
+            layout(set = 0, binding = 3) readonly buffer Palette {
+                MaterialStyle palette[];
+            };
+
+            // `extent` is the size of the window in cells, which may be less than the image.
+            layout(push_constant) uniform PushConstants {
+                vec2 extent;
+                uint particle_count;
             } push;
 
-            const float maxSpeed = 10.0;
-            const float friction = -2.0;
-            const float gravity = 9.8; // Constant gravity force downwards
-            const float ground_damping = 0.7; // Controls how much speed is lost on hitting the ground
+            // Keep in sync with `cs`.
+            const float DEAD = 1.0e6;
 
             void main() {
                 const uint index = gl_GlobalInvocationID.x;
 
-                vec2 vel = vertices[index].vel;
+                if (index >= push.particle_count || vertices[index].pos.x >= DEAD) {
+                    return;
+                }
+
+                VertexData v = vertices[index];
+                ivec2 cell = ivec2(floor((v.pos * 0.5 + 0.5) * push.extent));
+                if (any(lessThan(cell, ivec2(0))) || any(greaterThanEqual(cell, imageSize(grid)))) {
+                    return;
+                }
+
+                MaterialStyle style = palette[material_id(v.material)];
+                vec4 color = material_color(style, v.material, v.vel, v.heat, params.max_speed);
+                imageStore(grid, cell, color);
+            }
+        ",
+    }
+}
+
+// Vertex shader of a quad covering the whole window, drawn as a 4 vertex triangle strip.
+pub mod quad_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec2 uv;
+
+            void main() {
+                uv = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+                gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+// Fragment shader drawing the grid written by `grid_cs`, also used for the trails. `uv_scale`
+// maps the window onto the part of the image the window covers.
+pub mod grid_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 fragColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D grid;
+
+            layout(push_constant) uniform PushConstants {
+                vec2 uv_scale;
+            } push;
+
+            void main() {
+                fragColor = texture(grid, uv * push.uv_scale);
+            }
+        ",
+    }
+}
+
+// Bright-pass of the bloom: keeps the part of the scene brighter than `threshold`, downsampling
+// it to the size of `dst` with the linear filter of the sampler.
+pub mod bloom_bright_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D src;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D dst;
+
+            layout(push_constant) uniform PushConstants {
+                float threshold;
+            } push;
+
+            void main() {
+                ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(texel, size))) {
+                    return;
+                }
+
+                vec3 color = texture(src, (vec2(texel) + 0.5) / vec2(size)).rgb;
+                float brightness = max(color.r, max(color.g, color.b));
+                // Scale rather than subtract, so the glow keeps the hue of the material.
+                float excess = max(brightness - push.threshold, 0.0) / max(brightness, 1.0e-4);
+
+                imageStore(dst, texel, vec4(color * excess, 1.0));
+            }
+        ",
+    }
+}
+
+// One direction of a separable gaussian blur from `src` into `dst`, which have the same size.
+pub mod bloom_blur_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
 
-                // Update velocity with gravity (pulling downwards)
-                vel.y += push.delta_time * gravity;
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
 
-                // Update position
-                vec2 pos = vertices[index].pos + push.delta_time * vel;
+            layout(set = 0, binding = 0) uniform sampler2D src;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D dst;
+
+            // (1, 0) for the horizontal pass, (0, 1) for the vertical one.
+            layout(push_constant) uniform PushConstants {
+                ivec2 direction;
+            } push;
 
-                // Bounce particle off screen borders.
-                if (abs(pos.x) > 1.0) {
-                    vel.x = sign(pos.x) * (-0.95 * abs(vel.x) - 0.0001);
-                    pos.x = clamp(pos.x, -1.0, 1.0);
+            const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+            void main() {
+                ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(dst);
+                if (any(greaterThanEqual(texel, size))) {
+                    return;
                 }
 
-                if (pos.y < -1.0) {
-                    // Hitting the ground: dampen vertical velocity
-                    vel.y *= -ground_damping;
-                    pos.y = -1.0;
-                } else if (pos.y > 1.0) {
-                    // Top border: prevent escape
-                    vel.y = -0.95 * abs(vel.y);
-                    pos.y = 1.0;
+                vec3 color = texelFetch(src, texel, 0).rgb * WEIGHTS[0];
+                for (int i = 1; i < 5; i++) {
+                    ivec2 offset = push.direction * i;
+                    color += texelFetch(src, clamp(texel + offset, ivec2(0), size - 1), 0).rgb * WEIGHTS[i];
+                    color += texelFetch(src, clamp(texel - offset, ivec2(0), size - 1), 0).rgb * WEIGHTS[i];
+                }
+
+                imageStore(dst, texel, vec4(color, 1.0));
+            }
+        ",
+    }
+}
+
+// Fragment shader drawing the HDR scene to the swapchain, with the blurred bloom added on top.
+pub mod composite_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 fragColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+            layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+            layout(push_constant) uniform PushConstants {
+                float bloom_intensity;
+            } push;
+
+            void main() {
+                vec3 color = texture(scene, uv).rgb;
+                // The bloom image is left unwritten while bloom is off.
+                if (push.bloom_intensity > 0.0) {
+                    color += texture(bloom, uv).rgb * push.bloom_intensity;
+                }
+                fragColor = vec4(color, 1.0);
+            }
+        ",
+    }
+}
+
+// Fragment shader fading out the trails, blended over them so that what is already drawn keeps
+// `1.0 - fade` of its color.
+pub mod fade_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec4 fragColor;
+
+            layout(push_constant) uniform PushConstants {
+                float fade;
+            } push;
+
+            void main() {
+                fragColor = vec4(0.0, 0.0, 0.0, push.fade);
+            }
+        ",
+    }
+}
+
+// Vertex shader of the debug views, coloring particles by one simulation quantity instead of by
+// material. Drawn with `fs`, like `vs`. Keep the view ids in sync with `DebugView`.
+pub mod debug_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        include: ["shaders"],
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 pos;
+            layout(location = 1) in vec2 vel;
+            layout(location = 2) in uint material;
+            layout(location = 3) in float heat;
+
+            layout(location = 0) out vec4 outColor;
+
+            #define SIM_PARAMS_SET 0
+            #define SIM_PARAMS_BINDING 0
+            #include <sim_params.glsl>
+            #include <material.glsl>
+
+            // SPH density of each particle, written by `neighbour_cs`.
+            layout(set = 0, binding = 1) readonly buffer Densities {
+                float densities[];
+            };
+
+            layout(push_constant) uniform PushConstants {
+                uint view;
+                uint particle_count;
+                // Zero while SPH is off, as `densities` is then left unwritten.
+                float rest_density;
+            } push;
+
+            const uint VIEW_VELOCITY = 1;
+            const uint VIEW_TEMPERATURE = 2;
+            const uint VIEW_DENSITY = 3;
+            const uint VIEW_RESTING = 4;
+            const uint VIEW_INDEX = 5;
+            const uint VIEW_MATERIAL = 6;
+
+            // Speed below which a particle counts as resting, in normalized device coordinates
+            // per second.
+            const float REST_SPEED = 0.05;
+            const float PI = 3.14159265;
+
+            vec3 hue(float h) {
+                return clamp(abs(fract(h + vec3(0.0, 2.0, 1.0) / 3.0) * 6.0 - 3.0) - 1.0, 0.0, 1.0);
+            }
+
+            vec3 velocity_color() {
+                float speed = clamp(length(vel) / params.max_speed, 0.0, 1.0);
+                return hue(atan(vel.y, vel.x) / (2.0 * PI) + 0.5) * sqrt(speed);
+            }
+
+            // Black through red and yellow to white.
+            vec3 temperature_color() {
+                float t = clamp(heat, 0.0, 1.0) * 3.0;
+                return clamp(vec3(t, t - 1.0, t - 2.0), 0.0, 1.0);
+            }
+
+            // Blue below the rest density, white at it and red above.
+            vec3 density_color() {
+                uint index = uint(gl_VertexIndex);
+                if (push.rest_density <= 0.0 || index >= uint(densities.length())) {
+                    return vec3(0.3);
                 }
+                float d = densities[index] / push.rest_density - 1.0;
+                return d < 0.0
+                    ? mix(vec3(1.0), vec3(0.1, 0.3, 1.0), clamp(-d, 0.0, 1.0))
+                    : mix(vec3(1.0), vec3(1.0, 0.1, 0.1), clamp(d, 0.0, 1.0));
+            }
 
-                // Apply friction
-                vel *= exp(friction * push.delta_time);
+            // Resting particles are blue and moving ones orange.
+            vec3 resting_color() {
+                return length(vel) < REST_SPEED ? vec3(0.2, 0.4, 1.0) : vec3(1.0, 0.6, 0.1);
+            }
+
+            void main() {
+                gl_Position = vec4(pos, 0.0, 1.0);
+                gl_PointSize = 1.0;
 
-                // Enforce max speed
-                if (length(vel) > maxSpeed) {
-                    vel = maxSpeed * normalize(vel);
+                vec3 color;
+                switch (push.view) {
+                    case VIEW_VELOCITY:
+                        color = velocity_color();
+                        break;
+                    case VIEW_TEMPERATURE:
+                        color = temperature_color();
+                        break;
+                    case VIEW_DENSITY:
+                        color = density_color();
+                        break;
+                    case VIEW_RESTING:
+                        color = resting_color();
+                        break;
+                    case VIEW_INDEX:
+                        color = hue(float(gl_VertexIndex) / float(max(push.particle_count, 1u)));
+                        break;
+                    case VIEW_MATERIAL:
+                        // Golden ratio steps keep neighbouring ids apart.
+                        color = hue(float(material_id(material)) * 0.618034);
+                        break;
+                    default:
+                        color = vec3(1.0, 0.0, 1.0);
                 }
 
-                // Store updated values
-                vertices[index].pos = pos;
-                vertices[index].vel = vel;
+                outColor = vec4(color, 1.0);
             }
+        ",
+    }
+}
 
+// Shaders of the HUD, drawn over the finished frame. Every glyph is a quad carrying its pixels,
+// so no font texture is needed. See `font.rs`.
+pub mod hud_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 pos;
+            layout(location = 1) in vec2 texel;
+            layout(location = 2) in uvec2 glyph;
+            layout(location = 3) in vec4 color;
+
+            layout(location = 0) out vec2 outTexel;
+            layout(location = 1) flat out uvec2 outGlyph;
+            layout(location = 2) out vec4 outColor;
+
+            void main() {
+                gl_Position = vec4(pos, 0.0, 1.0);
+                outTexel = texel;
+                outGlyph = glyph;
+                outColor = color;
+            }
+        ",
+    }
+}
+
+pub mod hud_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 texel;
+            layout(location = 1) flat in uvec2 glyph;
+            layout(location = 2) in vec4 color;
+
+            layout(location = 0) out vec4 fragColor;
+
+            // Keep in sync with `font.rs`.
+            const uint GLYPH_WIDTH = 5;
+            const uint GLYPH_HEIGHT = 7;
+
+            void main() {
+                uvec2 pixel = min(uvec2(texel), uvec2(GLYPH_WIDTH - 1, GLYPH_HEIGHT - 1));
+                uint bit = pixel.y * GLYPH_WIDTH + pixel.x;
+                uint word = bit < 32 ? glyph.x : glyph.y;
+                if (((word >> (bit % 32)) & 1u) == 0) {
+                    discard;
+                }
+                fragColor = color;
+            }
         ",
     }
 }
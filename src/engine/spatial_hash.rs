@@ -0,0 +1,281 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{
+        AllocateBufferError, Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
+    },
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    DeviceSize, Validated,
+};
+
+use super::{error::Error, physics::SphParams, shaders::neighbour_cs, MyVertex};
+
+// Number of buckets particles are hashed into. `neighbour_cs` scans it in blocks of 1024 and then
+// scans the 1024 block sums in a single workgroup, so it must be exactly 1024 * 1024.
+const TABLE_SIZE: u32 = 1024 * 1024;
+
+// Stages of `neighbour_cs`, keep in sync with the constants in the shader.
+const STAGE_COUNT: u32 = 0;
+const STAGE_SCAN_BLOCKS: u32 = 1;
+const STAGE_SCAN_SUMS: u32 = 2;
+const STAGE_ADD_OFFSETS: u32 = 3;
+const STAGE_SCATTER: u32 = 4;
+const STAGE_COLLIDE: u32 = 5;
+const STAGE_DENSITY: u32 = 6;
+const STAGE_FORCES: u32 = 7;
+
+/// Neighbour lookup for free particles. The particles are counting-sorted by the hash of the
+/// cell they are in, which lets each particle find the ones near it by only looking at the cells
+/// around it. Used for collisions and SPH fluids.
+pub struct SpatialHash {
+    pipeline: Arc<ComputePipeline>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    counts: Subbuffer<[u32]>,
+    starts: Subbuffer<[u32]>,
+    block_sums: Subbuffer<[u32]>,
+    // The vertex buffer and the buffers sized to it that the descriptor set was made for.
+    vertices: Subbuffer<[MyVertex]>,
+    buffers: ParticleBuffers,
+    descriptor_set: Arc<DescriptorSet>,
+}
+
+/// The buffers `SpatialHash` needs for every particle. They are allocated by `DynMemoryManager`
+/// along with the vertex buffer, so running out of memory while growing it leaves both as they
+/// were.
+#[derive(Clone)]
+pub struct ParticleBuffers {
+    sorted: Subbuffer<[MyVertex]>,
+    sorted_ids: Subbuffer<[u32]>,
+    ranks: Subbuffer<[u32]>,
+    densities: Subbuffer<[f32]>,
+}
+
+impl ParticleBuffers {
+    pub fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        capacity: u32,
+    ) -> Result<Self, Validated<AllocateBufferError>> {
+        let len = capacity as DeviceSize;
+        Ok(Self {
+            sorted: new_storage_buffer(memory_allocator, len)?,
+            sorted_ids: new_storage_buffer(memory_allocator, len)?,
+            ranks: new_storage_buffer(memory_allocator, len)?,
+            densities: new_storage_buffer(memory_allocator, len)?,
+        })
+    }
+
+    /// SPH density of every particle, indexed like the vertex buffer. Only written while SPH is
+    /// on.
+    pub fn densities(&self) -> Subbuffer<[f32]> {
+        self.densities.clone()
+    }
+}
+
+// Values pushed to `neighbour_cs` besides the stage.
+struct StageParams {
+    particle_count: u32,
+    cell_size: f32,
+    delta_time: f32,
+    sph: SphParams,
+    particle_mass: f32,
+}
+
+impl SpatialHash {
+    /// `buffers` must be the ones allocated along with `vertices`.
+    pub fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        pipeline: Arc<ComputePipeline>,
+        vertices: &Subbuffer<[MyVertex]>,
+        buffers: &ParticleBuffers,
+    ) -> Result<Self, Error> {
+        let counts = new_storage_buffer(memory_allocator, TABLE_SIZE as DeviceSize)?;
+        let starts = new_storage_buffer(memory_allocator, TABLE_SIZE as DeviceSize)?;
+        let block_sums = new_storage_buffer(memory_allocator, (TABLE_SIZE / 1024) as DeviceSize)?;
+        let descriptor_set = new_descriptor_set(
+            &descriptor_set_allocator,
+            &pipeline,
+            [&counts, &starts, &block_sums],
+            vertices,
+            buffers,
+        )?;
+
+        Ok(Self {
+            pipeline,
+            descriptor_set_allocator,
+            counts,
+            starts,
+            block_sums,
+            vertices: vertices.clone(),
+            buffers: buffers.clone(),
+            descriptor_set,
+        })
+    }
+
+    /// Records the dispatches separating overlapping particles of `radius` into `builder`.
+    /// `vertices` is the current vertex buffer and `buffers` the ones allocated with it, they are
+    /// rebound whenever they were reallocated.
+    pub fn record_collisions(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertices: &Subbuffer<[MyVertex]>,
+        buffers: &ParticleBuffers,
+        particle_count: u32,
+        radius: f32,
+    ) -> Result<(), Error> {
+        let params = StageParams {
+            particle_count,
+            cell_size: 2.0 * radius,
+            delta_time: 0.0,
+            sph: SphParams::default(),
+            particle_mass: 0.0,
+        };
+        self.record_stages(builder, vertices, buffers, &params, &[STAGE_COLLIDE])
+    }
+
+    /// Records the SPH density and force passes into `builder`, which update the velocities of
+    /// the particles before they are integrated. The mass of a particle is chosen so that
+    /// particles `2 * radius` apart are at density 1.
+    pub fn record_sph(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertices: &Subbuffer<[MyVertex]>,
+        buffers: &ParticleBuffers,
+        particle_count: u32,
+        radius: f32,
+        sph: &SphParams,
+        delta_time: f32,
+    ) -> Result<(), Error> {
+        let params = StageParams {
+            particle_count,
+            cell_size: sph.smoothing_radius,
+            delta_time,
+            sph: *sph,
+            particle_mass: 4.0 * radius * radius,
+        };
+        self.record_stages(
+            builder,
+            vertices,
+            buffers,
+            &params,
+            &[STAGE_DENSITY, STAGE_FORCES],
+        )
+    }
+
+    // Sorts the particles into cells of `params.cell_size`, then runs the given per-particle
+    // stages.
+    fn record_stages(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertices: &Subbuffer<[MyVertex]>,
+        buffers: &ParticleBuffers,
+        params: &StageParams,
+        stages: &[u32],
+    ) -> Result<(), Error> {
+        if !Arc::ptr_eq(self.vertices.buffer(), vertices.buffer()) {
+            self.descriptor_set = new_descriptor_set(
+                &self.descriptor_set_allocator,
+                &self.pipeline,
+                [&self.counts, &self.starts, &self.block_sums],
+                vertices,
+                buffers,
+            )?;
+            self.vertices = vertices.clone();
+            self.buffers = buffers.clone();
+        }
+
+        if params.particle_count == 0 {
+            return Ok(());
+        }
+
+        let particle_groups = params.particle_count.div_ceil(1024);
+        let table_groups = TABLE_SIZE / 1024;
+
+        builder
+            .fill_buffer(self.counts.clone(), 0)?
+            .bind_pipeline_compute(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )?;
+
+        let sort = [
+            (STAGE_COUNT, particle_groups),
+            (STAGE_SCAN_BLOCKS, table_groups),
+            (STAGE_SCAN_SUMS, 1),
+            (STAGE_ADD_OFFSETS, table_groups),
+            (STAGE_SCATTER, particle_groups),
+        ];
+        let per_particle = stages.iter().map(|&stage| (stage, particle_groups));
+
+        for (stage, groups) in sort.into_iter().chain(per_particle) {
+            builder.push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                neighbour_cs::PushConstants {
+                    stage,
+                    particle_count: params.particle_count,
+                    cell_size: params.cell_size,
+                    delta_time: params.delta_time,
+                    rest_density: params.sph.rest_density,
+                    stiffness: params.sph.stiffness,
+                    viscosity: params.sph.viscosity,
+                    particle_mass: params.particle_mass,
+                },
+            )?;
+            unsafe { builder.dispatch([groups, 1, 1]) }?;
+        }
+        Ok(())
+    }
+}
+
+// Binds the hash table, given as its counts, starts and block sums, along with the particles.
+fn new_descriptor_set(
+    descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    pipeline: &Arc<ComputePipeline>,
+    [counts, starts, block_sums]: [&Subbuffer<[u32]>; 3],
+    vertices: &Subbuffer<[MyVertex]>,
+    buffers: &ParticleBuffers,
+) -> Result<Arc<DescriptorSet>, Error> {
+    let descriptor_set = DescriptorSet::new(
+        descriptor_set_allocator.clone(),
+        pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::buffer(0, vertices.clone()),
+            WriteDescriptorSet::buffer(1, buffers.sorted.clone()),
+            WriteDescriptorSet::buffer(2, buffers.sorted_ids.clone()),
+            WriteDescriptorSet::buffer(3, buffers.ranks.clone()),
+            WriteDescriptorSet::buffer(4, counts.clone()),
+            WriteDescriptorSet::buffer(5, starts.clone()),
+            WriteDescriptorSet::buffer(6, block_sums.clone()),
+            WriteDescriptorSet::buffer(7, buffers.densities.clone()),
+        ],
+        [],
+    )?;
+    Ok(descriptor_set)
+}
+
+fn new_storage_buffer<T: BufferContents>(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    len: DeviceSize,
+) -> Result<Subbuffer<[T]>, Validated<AllocateBufferError>> {
+    Buffer::new_slice::<T>(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        len,
+    )
+}
@@ -0,0 +1,20 @@
+/// What the left mouse button does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    /// Spawn particles of the selected material under the cursor while held.
+    Spawn,
+    /// Place an attractor on click, or a repeller with shift held. Right click removes one.
+    Attractor,
+    /// Paint wind blowing in the direction the cursor is dragged. Right drag erases it.
+    Wind,
+    /// Drop a crate on click, or a rock with shift held.
+    Body,
+    /// Set off an explosion on click.
+    Explosion,
+    /// Paint walls while dragging, or draw a straight line with shift held and a rectangle with
+    /// control held. Right drag erases walls.
+    Wall,
+    /// Place an emitter of the selected material on click, or a sink with shift held. Right click
+    /// removes one.
+    Emitter,
+}
@@ -0,0 +1,406 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{
+        AutoCommandBufferBuilder, BlitImageInfo, ClearColorImageInfo, PrimaryAutoCommandBuffer,
+        RenderPassBeginInfo,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::DeviceOwned,
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::AttachmentBlend,
+            input_assembly::PrimitiveTopology,
+            vertex_input::{Vertex, VertexDefinition, VertexInputState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
+    shader::EntryPoint,
+};
+use winit::dpi::PhysicalSize;
+
+use super::{
+    error::Error,
+    pipeline::new_graphics_pipeline,
+    render::{TrailBlend, TrailSettings},
+    shaders::{fade_fs, grid_fs, quad_vs},
+    MyVertex,
+};
+
+// Same as the scene image, so the trails of emissive materials glow.
+const TRAIL_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// The image particles are accumulated in while trails are on. Every frame it is faded, the
+/// particles are drawn on top, and the result is drawn in the scene in place of the particles.
+#[derive(Clone)]
+pub struct Trails {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    // Keeps the contents of the image, unlike the scene render pass.
+    trail_pass: Arc<RenderPass>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    quad_vs: EntryPoint,
+    fade_fs: EntryPoint,
+    grid_fs: EntryPoint,
+    sampler: Arc<Sampler>,
+    // Whether the image holds trails, rather than whatever was in memory when it was created.
+    cleared: bool,
+    // The image before the last resize, stretched into the new one on the next frame.
+    previous: Option<Arc<ImageView>>,
+    // The rest depends on the window size.
+    image: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+    fade_pipeline: Arc<GraphicsPipeline>,
+    additive_pipeline: Arc<GraphicsPipeline>,
+    alpha_pipeline: Arc<GraphicsPipeline>,
+    draw_pipeline: Arc<GraphicsPipeline>,
+    draw_set: Arc<DescriptorSet>,
+}
+
+impl Trails {
+    /// `vs` and `fs` are the shaders particles are drawn with, and the trails are drawn in
+    /// `scene_pass`.
+    pub fn new(
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        scene_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+        vs: &EntryPoint,
+        fs: &EntryPoint,
+    ) -> Result<Self, Error> {
+        let device = memory_allocator.device().clone();
+        let trail_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    format: TRAIL_FORMAT,
+                    samples: 1,
+                    load_op: Load,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )?;
+        let quad_vs = quad_vs::load(device.clone())?.entry_point("main").unwrap();
+        let fade_fs = fade_fs::load(device.clone())?.entry_point("main").unwrap();
+        let grid_fs = grid_fs::load(device.clone())?.entry_point("main").unwrap();
+        // The image is the size of the window, so there is nothing to filter.
+        let sampler = Sampler::new(device.clone(), SamplerCreateInfo::default())?;
+
+        let image = new_trail_image(&memory_allocator, window_size)?;
+        let framebuffer = new_framebuffer(&trail_pass, &image)?;
+        let [fade_pipeline, additive_pipeline, alpha_pipeline] =
+            new_accumulate_pipelines(&trail_pass, window_size, vs, fs, &quad_vs, &fade_fs)?;
+        let draw_pipeline = new_draw_pipeline(scene_pass, window_size, &quad_vs, &grid_fs)?;
+        let draw_set = new_draw_set(&descriptor_set_allocator, &draw_pipeline, &image, &sampler)?;
+
+        Ok(Self {
+            memory_allocator,
+            descriptor_set_allocator,
+            trail_pass,
+            vs: vs.clone(),
+            fs: fs.clone(),
+            quad_vs,
+            fade_fs,
+            grid_fs,
+            sampler,
+            cleared: false,
+            previous: None,
+            image,
+            framebuffer,
+            fade_pipeline,
+            additive_pipeline,
+            alpha_pipeline,
+            draw_pipeline,
+            draw_set,
+        })
+    }
+
+    /// Recreates the image and pipelines for a new window size. The trails drawn so far are kept,
+    /// stretched to the new size.
+    pub fn resize(
+        &mut self,
+        scene_pass: &Arc<RenderPass>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<(), Error> {
+        // Several resizes can happen between two frames, in which case the oldest image is the
+        // one holding the trails.
+        if self.previous.is_none() && self.cleared {
+            self.previous = Some(self.image.clone());
+        }
+
+        self.image = new_trail_image(&self.memory_allocator, window_size)?;
+        self.framebuffer = new_framebuffer(&self.trail_pass, &self.image)?;
+        [
+            self.fade_pipeline,
+            self.additive_pipeline,
+            self.alpha_pipeline,
+        ] = new_accumulate_pipelines(
+            &self.trail_pass,
+            window_size,
+            &self.vs,
+            &self.fs,
+            &self.quad_vs,
+            &self.fade_fs,
+        )?;
+        self.draw_pipeline =
+            new_draw_pipeline(scene_pass, window_size, &self.quad_vs, &self.grid_fs)?;
+        self.draw_set = new_draw_set(
+            &self.descriptor_set_allocator,
+            &self.draw_pipeline,
+            &self.image,
+            &self.sampler,
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the shaders particles are drawn with, rebuilding the pipelines using them. On
+    /// failure the current ones are kept.
+    #[cfg(feature = "shader-reload")]
+    pub fn set_shaders(
+        &mut self,
+        window_size: PhysicalSize<u32>,
+        vs: &EntryPoint,
+        fs: &EntryPoint,
+    ) -> Result<(), Error> {
+        [
+            self.fade_pipeline,
+            self.additive_pipeline,
+            self.alpha_pipeline,
+        ] = new_accumulate_pipelines(
+            &self.trail_pass,
+            window_size,
+            vs,
+            fs,
+            &self.quad_vs,
+            &self.fade_fs,
+        )?;
+        self.vs = vs.clone();
+        self.fs = fs.clone();
+        Ok(())
+    }
+
+    /// Forgets the trails, so they start out empty the next time they are drawn.
+    pub fn reset(&mut self) {
+        self.cleared = false;
+        self.previous = None;
+    }
+
+    /// Records fading the trails and drawing the particles on top. Must be recorded outside of
+    /// a render pass.
+    pub fn record_accumulate(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertices: &Subbuffer<[MyVertex]>,
+        particle_count: u32,
+        render_params_set: Arc<DescriptorSet>,
+        settings: &TrailSettings,
+        delta_time: f32,
+    ) -> Result<(), Error> {
+        if let Some(previous) = self.previous.take() {
+            builder.blit_image(BlitImageInfo {
+                filter: Filter::Linear,
+                ..BlitImageInfo::images(previous.image().clone(), self.image.image().clone())
+            })?;
+        } else if !self.cleared {
+            builder.clear_color_image(ClearColorImageInfo {
+                clear_value: [0.0, 0.0, 0.0, 1.0].into(),
+                ..ClearColorImageInfo::image(self.image.image().clone())
+            })?;
+        }
+        self.cleared = true;
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+                },
+                Default::default(),
+            )?
+            .bind_pipeline_graphics(self.fade_pipeline.clone())?
+            .push_constants(
+                self.fade_pipeline.layout().clone(),
+                0,
+                fade_fs::PushConstants {
+                    fade: 1.0 - (-settings.fade * delta_time).exp(),
+                },
+            )?;
+        unsafe { builder.draw(4, 1, 0, 0) }?;
+
+        let pipeline = match settings.blend {
+            TrailBlend::Additive => &self.additive_pipeline,
+            TrailBlend::Alpha => &self.alpha_pipeline,
+        };
+        builder
+            .bind_pipeline_graphics(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                render_params_set,
+            )?
+            .bind_vertex_buffers(0, vertices.clone())?;
+        unsafe { builder.draw(particle_count, 1, 0, 0) }?;
+
+        builder.end_render_pass(Default::default())?;
+        Ok(())
+    }
+
+    /// Records drawing the trails over the whole window. Must be recorded inside the scene render
+    /// pass.
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), Error> {
+        let layout = self.draw_pipeline.layout();
+        builder
+            .bind_pipeline_graphics(self.draw_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.draw_set.clone(),
+            )?
+            .push_constants(
+                layout.clone(),
+                0,
+                grid_fs::PushConstants {
+                    uv_scale: [1.0, 1.0],
+                },
+            )?;
+        unsafe { builder.draw(4, 1, 0, 0) }?;
+        Ok(())
+    }
+}
+
+fn new_trail_image(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    window_size: PhysicalSize<u32>,
+) -> Result<Arc<ImageView>, Error> {
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: TRAIL_FORMAT,
+            extent: [window_size.width.max(1), window_size.height.max(1), 1],
+            usage: ImageUsage::COLOR_ATTACHMENT
+                | ImageUsage::SAMPLED
+                | ImageUsage::TRANSFER_SRC
+                | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+    Ok(ImageView::new_default(image)?)
+}
+
+fn new_framebuffer(
+    trail_pass: &Arc<RenderPass>,
+    image: &Arc<ImageView>,
+) -> Result<Arc<Framebuffer>, Error> {
+    let framebuffer = Framebuffer::new(
+        trail_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: vec![image.clone()],
+            ..Default::default()
+        },
+    )?;
+    Ok(framebuffer)
+}
+
+// Pipelines fading the trails, and drawing particles over them with either blend mode.
+fn new_accumulate_pipelines(
+    trail_pass: &Arc<RenderPass>,
+    window_size: PhysicalSize<u32>,
+    vs: &EntryPoint,
+    fs: &EntryPoint,
+    quad_vs: &EntryPoint,
+    fade_fs: &EntryPoint,
+) -> Result<[Arc<GraphicsPipeline>; 3], Error> {
+    let pipeline = |shaders: [&EntryPoint; 2], vertex_input_state, topology, blend| {
+        new_graphics_pipeline(
+            window_size,
+            trail_pass,
+            trail_pass.device(),
+            shaders,
+            vertex_input_state,
+            topology,
+            Some(blend),
+        )
+    };
+    let particles = MyVertex::per_vertex().definition(vs)?;
+
+    Ok([
+        pipeline(
+            [quad_vs, fade_fs],
+            VertexInputState::new(),
+            PrimitiveTopology::TriangleStrip,
+            AttachmentBlend::alpha(),
+        )?,
+        pipeline(
+            [vs, fs],
+            particles.clone(),
+            PrimitiveTopology::PointList,
+            AttachmentBlend::additive(),
+        )?,
+        pipeline(
+            [vs, fs],
+            particles,
+            PrimitiveTopology::PointList,
+            AttachmentBlend::alpha(),
+        )?,
+    ])
+}
+
+fn new_draw_pipeline(
+    scene_pass: &Arc<RenderPass>,
+    window_size: PhysicalSize<u32>,
+    quad_vs: &EntryPoint,
+    grid_fs: &EntryPoint,
+) -> Result<Arc<GraphicsPipeline>, Error> {
+    new_graphics_pipeline(
+        window_size,
+        scene_pass,
+        scene_pass.device(),
+        [quad_vs, grid_fs],
+        // The quad's corners are computed from the vertex index.
+        VertexInputState::new(),
+        PrimitiveTopology::TriangleStrip,
+        None,
+    )
+}
+
+fn new_draw_set(
+    descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    draw_pipeline: &Arc<GraphicsPipeline>,
+    image: &Arc<ImageView>,
+    sampler: &Arc<Sampler>,
+) -> Result<Arc<DescriptorSet>, Error> {
+    let set = DescriptorSet::new(
+        descriptor_set_allocator.clone(),
+        draw_pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            image.clone(),
+            sampler.clone(),
+        )],
+        [],
+    )?;
+    Ok(set)
+}
@@ -0,0 +1,208 @@
+use std::ops::RangeInclusive;
+
+use winit::event::MouseButton;
+
+use super::hud::{text_width, Canvas, MARGIN, PANEL_COLOR, TEXT_COLOR, TEXT_HEIGHT};
+
+// Size of the panel and its widgets, in screen pixels.
+const PANEL_WIDTH: f32 = 300.0;
+const ROW_HEIGHT: f32 = 22.0;
+const ROW_SPACING: f32 = 4.0;
+
+const WIDGET_COLOR: [f32; 4] = [0.25, 0.25, 0.3, 0.9];
+const HOVERED_COLOR: [f32; 4] = [0.35, 0.35, 0.45, 0.9];
+const SELECTED_COLOR: [f32; 4] = [0.3, 0.5, 0.8, 0.9];
+const HEADING_COLOR: [f32; 4] = [1.0, 0.8, 0.4, 1.0];
+
+/// A small immediate-mode UI: the panel in the top right corner is declared anew every frame,
+/// each widget drawing itself and reporting whether it was used. While the cursor is over the
+/// panel or dragging one of its sliders, the mouse belongs to the panel rather than the tools.
+pub struct Ui {
+    visible: bool,
+    // Cursor position in screen pixels.
+    cursor: [f32; 2],
+    // Whether the left button went down over the panel since the last frame, until a widget
+    // takes the click.
+    clicked: bool,
+    held: bool,
+    // Index of the slider being dragged.
+    dragging: Option<usize>,
+    // Area covered by the panel on the last frame.
+    area: Option<[[f32; 2]; 2]>,
+}
+
+/// The panel being declared, see `Ui::panel`.
+pub struct Panel<'a> {
+    ui: &'a mut Ui,
+    canvas: &'a mut Canvas,
+    background: usize,
+    left: f32,
+    y: f32,
+    next_id: usize,
+}
+
+impl Ui {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            cursor: [0.0, 0.0],
+            clicked: false,
+            held: false,
+            dragging: None,
+            area: None,
+        }
+    }
+
+    pub fn cursor_moved(&mut self, pos: [f32; 2]) {
+        self.cursor = pos;
+    }
+
+    /// Whether mouse input should go to the panel rather than the tools.
+    pub fn wants_mouse(&self) -> bool {
+        self.dragging.is_some() || self.area.is_some_and(|area| contains(area, self.cursor))
+    }
+
+    /// Handles a press or release of a mouse button, returning whether the panel took it.
+    pub fn mouse_input(&mut self, button: MouseButton, pressed: bool) -> bool {
+        if !pressed {
+            // Releases go to whoever got the press.
+            let held = button == MouseButton::Left && self.held;
+            if held {
+                self.held = false;
+                self.dragging = None;
+            }
+            return held;
+        }
+
+        if !self.visible || !self.wants_mouse() {
+            return false;
+        }
+        if button == MouseButton::Left {
+            self.clicked = true;
+            self.held = true;
+        }
+        true
+    }
+
+    /// Starts declaring the panel, which is drawn on `canvas`.
+    pub fn panel<'a>(&'a mut self, canvas: &'a mut Canvas, window_width: f32) -> Panel<'a> {
+        let background = canvas.reserve_rect();
+        let left = window_width - PANEL_WIDTH;
+        Panel {
+            ui: self,
+            canvas,
+            background,
+            left,
+            y: MARGIN,
+            next_id: 0,
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Shows or hides the panel. A hidden panel is forgotten, so it doesn't keep the mouse.
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.area = None;
+        self.dragging = None;
+        self.clicked = false;
+    }
+}
+
+impl Panel<'_> {
+    pub fn heading(&mut self, text: &str) {
+        let area = self.next_row();
+        self.canvas
+            .text([area[0][0], text_y(area)], text, HEADING_COLOR);
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let area = self.next_row();
+        self.canvas
+            .text([area[0][0], text_y(area)], text, TEXT_COLOR);
+    }
+
+    /// A button, highlighted while `selected`. Returns whether it was clicked.
+    pub fn button(&mut self, text: &str, selected: bool) -> bool {
+        let area = self.next_row();
+        let hovered = contains(area, self.ui.cursor);
+        let clicked = hovered && self.ui.clicked;
+        if clicked {
+            self.ui.clicked = false;
+        }
+
+        let color = match (selected, hovered) {
+            (true, _) => SELECTED_COLOR,
+            (false, true) => HOVERED_COLOR,
+            (false, false) => WIDGET_COLOR,
+        };
+        self.canvas.rect(area[0], area[1], color);
+        let x = (area[0][0] + area[1][0] - text_width(text)) / 2.0;
+        self.canvas.text([x, text_y(area)], text, TEXT_COLOR);
+        clicked
+    }
+
+    /// A slider setting `value` within `range`, showing `label` and the value. Returns whether
+    /// the value changed.
+    pub fn slider(&mut self, label: &str, value: &mut f32, range: RangeInclusive<f32>) -> bool {
+        let id = self.next_id;
+        self.next_id += 1;
+        let area = self.next_row();
+        if self.ui.clicked && contains(area, self.ui.cursor) {
+            self.ui.clicked = false;
+            self.ui.dragging = Some(id);
+        }
+
+        let (min, max) = (*range.start(), *range.end());
+        let previous = *value;
+        if self.ui.dragging == Some(id) {
+            let t = (self.ui.cursor[0] - area[0][0]) / (area[1][0] - area[0][0]);
+            *value = min + t.clamp(0.0, 1.0) * (max - min);
+        }
+
+        let t = ((*value - min) / (max - min)).clamp(0.0, 1.0);
+        let fill = area[0][0] + t * (area[1][0] - area[0][0]);
+        self.canvas.rect(area[0], area[1], WIDGET_COLOR);
+        self.canvas
+            .rect(area[0], [fill, area[1][1]], SELECTED_COLOR);
+        self.canvas.text(
+            [area[0][0] + MARGIN / 2.0, text_y(area)],
+            &format!("{label} {value:.3}"),
+            TEXT_COLOR,
+        );
+        *value != previous
+    }
+
+    /// Finishes the panel, drawing its background behind the widgets.
+    pub fn end(self) {
+        let area = [
+            [self.left, 0.0],
+            [self.left + PANEL_WIDTH, self.y - ROW_SPACING + MARGIN],
+        ];
+        self.canvas
+            .place_rect(self.background, area[0], area[1], PANEL_COLOR);
+        self.ui.area = Some(area);
+        // A click no widget took is dropped, rather than clicking whatever is there next frame.
+        self.ui.clicked = false;
+    }
+
+    fn next_row(&mut self) -> [[f32; 2]; 2] {
+        let area = [
+            [self.left + MARGIN, self.y],
+            [self.left + PANEL_WIDTH - MARGIN, self.y + ROW_HEIGHT],
+        ];
+        self.y += ROW_HEIGHT + ROW_SPACING;
+        area
+    }
+}
+
+// Top of text centered vertically in a row.
+fn text_y(area: [[f32; 2]; 2]) -> f32 {
+    area[0][1] + (ROW_HEIGHT - TEXT_HEIGHT) / 2.0
+}
+
+fn contains([min, max]: [[f32; 2]; 2], [x, y]: [f32; 2]) -> bool {
+    (min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y)
+}
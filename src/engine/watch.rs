@@ -0,0 +1,81 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+// Files are checked at most this often.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when files change by polling their modification times. Files that don't exist are
+/// watched too, and count as changed once they are created.
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+        Self {
+            files,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Whether any of the files changed since the last call, checking if `POLL_INTERVAL` passed.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        self.poll()
+    }
+
+    fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn notices_changes() {
+        let path = std::env::temp_dir().join(format!("sand-watch-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut watcher = FileWatcher::new([path.clone()]);
+        assert!(!watcher.poll());
+
+        fs::write(&path, "").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert!(watcher.poll());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.poll());
+    }
+}
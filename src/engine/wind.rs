@@ -0,0 +1,74 @@
+use serde::Deserialize;
+
+/// Number of cells along each axis of the wind field. Keep in sync with `WIND_GRID_SIZE` in `cs`.
+pub const WIND_GRID_SIZE: usize = 64;
+
+/// Settings of the wind tool.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindBrush {
+    /// Speed of painted wind, in normalized device coordinates per second.
+    pub strength: f32,
+    /// Brush radius in normalized device coordinates.
+    pub radius: f32,
+}
+
+impl Default for WindBrush {
+    fn default() -> Self {
+        Self {
+            strength: 3.0,
+            radius: 0.08,
+        }
+    }
+}
+
+/// A coarse grid of air velocities covering the whole world. Particles are dragged towards the
+/// velocity of the cell they are in.
+pub struct WindField {
+    cells: Vec<[f32; 2]>,
+}
+
+impl WindField {
+    pub fn new() -> Self {
+        Self {
+            cells: vec![[0.0, 0.0]; WIND_GRID_SIZE * WIND_GRID_SIZE],
+        }
+    }
+
+    /// Row-major cell velocities, as uploaded to `cs`.
+    pub fn cells(&self) -> &[[f32; 2]] {
+        &self.cells
+    }
+
+    /// Sets every cell whose center lies within `radius` of `pos` to `velocity`.
+    pub fn paint(&mut self, pos: [f32; 2], radius: f32, velocity: [f32; 2]) {
+        let cell_size = 2.0 / WIND_GRID_SIZE as f32;
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            let x = -1.0 + ((i % WIND_GRID_SIZE) as f32 + 0.5) * cell_size;
+            let y = -1.0 + ((i / WIND_GRID_SIZE) as f32 + 0.5) * cell_size;
+            if (x - pos[0]).hypot(y - pos[1]) <= radius {
+                *cell = velocity;
+            }
+        }
+    }
+
+    pub fn erase(&mut self, pos: [f32; 2], radius: f32) {
+        self.paint(pos, radius, [0.0, 0.0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_only_touches_cells_in_radius() {
+        let mut field = WindField::new();
+        field.paint([-1.0, -1.0], 0.03, [1.0, 0.0]);
+
+        let painted = field.cells().iter().filter(|c| **c == [1.0, 0.0]).count();
+        // Only the top left cell has its center that close to the corner.
+        assert_eq!(painted, 1);
+        assert_eq!(field.cells()[0], [1.0, 0.0]);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{env, error::Error, fs::File, path::Path, sync::Mutex};
+
+use tracing::warn;
+use tracing_subscriber::{
+    fmt::{self, format::FmtSpan},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+/// Environment variable setting which logs are shown, as in `RUST_LOG`: a level such as `debug`,
+/// or directives such as `info,rusty_sand_sim::engine::memory=trace`.
+pub const LOG_ENV: &str = "SAND_LOG";
+/// Environment variable naming a file the logs are also written to, as one JSON object per line.
+pub const LOG_FILE_ENV: &str = "SAND_LOG_FILE";
+
+const DEFAULT_FILTER: &str = "info";
+
+/// Installs the global logger, writing readable logs to stderr and, given `json_file`, JSON logs
+/// to that file. `filter` takes precedence over `LOG_ENV`, and both fall back to showing `info`.
+/// An invalid `filter` is an error, an invalid `LOG_ENV` is warned about and ignored.
+///
+/// At `debug`, every frame logs how long its phases took on the CPU as their spans close, in
+/// `time.busy`: `frame` is the whole frame, `acquire` waits for a swapchain image, `compute` and
+/// `render` record the simulation and drawing commands, and `present` submits them. The GPU runs
+/// the commands after `present`, so its time only shows up where the CPU waits for it, in
+/// `acquire`, or in `tick` without a window, which waits for every tick to finish.
+pub fn init(filter: Option<&str>, json_file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut invalid_env = None;
+    let filter = match filter {
+        Some(filter) => EnvFilter::try_new(filter)?,
+        None => match env::var(LOG_ENV) {
+            Ok(directives) => EnvFilter::try_new(directives).unwrap_or_else(|e| {
+                invalid_env = Some(e);
+                EnvFilter::new(DEFAULT_FILTER)
+            }),
+            Err(_) => EnvFilter::new(DEFAULT_FILTER),
+        },
+    };
+
+    let json = match json_file {
+        Some(path) => {
+            let file = File::create(path)?;
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_span_list(true)
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_writer(Mutex::new(file))
+                    .boxed(),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            fmt::layer()
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(std::io::stderr),
+        )
+        .with(json)
+        .try_init()?;
+
+    // Only now is there a logger to warn with.
+    if let Some(e) = invalid_env {
+        warn!("ignoring {LOG_ENV}, which is not a valid filter: {e}");
+    }
+    Ok(())
+}
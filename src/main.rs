@@ -1,17 +1,167 @@
-use std::error::Error;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
 
+use clap::Parser;
+use tracing::error;
+use vulkano::memory::MemoryHeapFlags;
 use winit::event_loop::EventLoop;
 
+mod cli;
 mod engine;
+mod logging;
 
-pub use engine::App;
+use cli::{BenchArgs, Cli, Command, HeadlessArgs, RunArgs};
+pub use engine::{App, Config, Error, DEFAULT_CONFIG_PATH};
 
-fn main() -> Result<(), impl Error> {
-    // The usual Vulkan initialization. Largely the same as the triangle example until further
-    // commentation is provided.
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let log_file = cli
+        .log_file
+        .clone()
+        .or_else(|| env::var_os(logging::LOG_FILE_ENV).map(PathBuf::from));
+    // There is no logger to report this with yet.
+    if let Err(e) = logging::init(cli.log.as_deref(), log_file.as_deref()) {
+        eprintln!("failed to set up logging: {e}");
+        return ExitCode::FAILURE;
+    }
 
-    let event_loop = EventLoop::new().unwrap();
-    let mut app = App::new(&event_loop);
+    // Only the default file may be missing or broken, one given explicitly has to load.
+    let config_path = cli
+        .config
+        .as_deref()
+        .unwrap_or(Path::new(DEFAULT_CONFIG_PATH));
+    let mut config = if cli.config.is_some() {
+        match Config::load(config_path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("failed to load {}: {e}", config_path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        Config::load_or_default(config_path)
+    };
+    let command = cli.command.unwrap_or(Command::Run(cli.run));
+    command.apply(&mut config);
 
-    event_loop.run_app(&mut app)
+    let result = match command {
+        Command::Run(args) => run(config, config_path, &args),
+        Command::Headless(args) => headless(config, &args),
+        Command::Bench(args) => bench(config, &args),
+        Command::ListDevices => list_devices(),
+        Command::Convert { input, output } => convert(&input, &output),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(config: Config, config_path: &Path, args: &RunArgs) -> Result<(), Error> {
+    let event_loop = EventLoop::new()?;
+    let mut app = App::new(&event_loop, config)?;
+    if let Some(scene) = &args.sim.scene {
+        app.load(scene)?;
+    }
+    // The options given here still win over the file when it is reloaded.
+    let overrides = Command::Run(args.clone());
+    app.watch_config(config_path.to_owned(), move |config| {
+        overrides.apply(config)
+    });
+    #[cfg(feature = "shader-reload")]
+    if let Some(dir) = &args.shaders {
+        app.watch_shaders(dir.clone());
+    }
+
+    event_loop.run_app(&mut app)?;
+    app.finish()
+}
+
+fn headless(config: Config, args: &HeadlessArgs) -> Result<(), Error> {
+    let mut app = App::new_headless(config)?;
+    if let Some(scene) = &args.sim.scene {
+        app.load(scene)?;
+    }
+
+    app.run_headless(args.ticks)?;
+    app.save(&args.output)
+}
+
+fn bench(config: Config, args: &BenchArgs) -> Result<(), Error> {
+    let mut app = App::new_headless(config)?;
+    match &args.sim.scene {
+        Some(scene) => app.load(scene)?,
+        None => app.spawn_grid(args.particles)?,
+    }
+
+    let start = Instant::now();
+    app.run_headless(args.ticks)?;
+    let elapsed = start.elapsed();
+
+    let per_tick = elapsed.as_secs_f64() / args.ticks.max(1) as f64;
+    println!(
+        "{} ticks with {} particles in {elapsed:.2?}: {:.3} ms per tick, {:.0} ticks per second",
+        args.ticks,
+        app.particle_count(),
+        per_tick * 1000.0,
+        1.0 / per_tick,
+    );
+    Ok(())
+}
+
+fn list_devices() -> Result<(), Error> {
+    let instance = engine::device::new_instance(None)?;
+    for (i, device) in engine::device::list(&instance)?.iter().enumerate() {
+        let properties = device.properties();
+        let device_memory: u64 = device
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+        println!(
+            "{i}: {} ({:?}, Vulkan {})",
+            properties.device_name,
+            properties.device_type,
+            device.api_version()
+        );
+        println!(
+            "    compute workgroups: up to {} invocations, {:?} in size, {:?} in count",
+            properties.max_compute_work_group_invocations,
+            properties.max_compute_work_group_size,
+            properties.max_compute_work_group_count,
+        );
+        println!(
+            "    compute shared memory: {} KiB, storage buffers: up to {} MiB, device memory: {} MiB",
+            properties.max_compute_shared_memory_size / 1024,
+            properties.max_storage_buffer_range / (1024 * 1024),
+            device_memory / (1024 * 1024),
+        );
+        if !engine::device::can_simulate(device) {
+            println!(
+                "    can't run the simulation, which needs workgroups of {} invocations",
+                engine::device::WORKGROUP_SIZE
+            );
+        }
+    }
+    Ok(())
+}
+
+fn convert(input: &Path, output: &Path) -> Result<(), Error> {
+    let snapshot = engine::save::Snapshot::load(input).map_err(|source| Error::Load {
+        path: input.to_owned(),
+        source,
+    })?;
+    snapshot.save(output).map_err(|source| Error::Save {
+        path: output.to_owned(),
+        source,
+    })
 }